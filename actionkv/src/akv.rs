@@ -0,0 +1,251 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    ops::Bound,
+    process::ExitCode,
+};
+
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use libactionkv::{
+    ActionKV, KeyValuePair, RecordKind, Recovery, Tail,
+    args::{Args, Command, Encoding},
+    backup, export,
+};
+
+/// Exit code for a key that does not exist, like `grep` finding no match.
+const NOT_FOUND: u8 = 1;
+
+/// Exit code for everything else that goes wrong.
+const FAILURE: u8 = 2;
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("akv: {:#}", err);
+            ExitCode::from(FAILURE)
+        }
+    }
+}
+
+fn run(args: Args) -> Result<ExitCode> {
+    let Args {
+        fname,
+        encoding,
+        recover,
+        command,
+    } = args;
+
+    // opening the store would create the directory restore writes to
+    if let Command::Restore { snapshot } = &command {
+        let manifest = backup::restore(snapshot, &fname)?;
+        eprintln!(
+            "restored {} keys, {} records",
+            manifest.keys, manifest.records
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // follows whatever other processes write, so needs no index of its own,
+    // nor write access to the store
+    if let Command::Tail { from } = command {
+        let mut out = io::stdout().lock();
+        for record in Tail::follow(&fname, from)? {
+            let (position, record) = record?;
+            write!(out, "{}:{}\t", position.segment, position.offset)?;
+            match record.kind {
+                RecordKind::Put => {
+                    out.write_all(b"put\t")?;
+                    write_pair(&mut out, encoding, &record.kv)?;
+                }
+                _ => {
+                    out.write_all(b"delete\t")?;
+                    out.write_all(&encoding.encode(&record.kv.key)?)?;
+                    out.write_all(b"\n")?;
+                }
+            }
+            out.flush()?;
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // commands that only read must not create a missing store, nor need
+    // write access to an existing one; recovering may have to cut the log
+    let read_only = match command {
+        Command::Fsck => true,
+        Command::Get { .. }
+        | Command::List { .. }
+        | Command::Scan { .. }
+        | Command::Dump
+        | Command::Export { .. }
+        | Command::Stats => !recover,
+        _ => false,
+    };
+    let mut a = match read_only {
+        true => ActionKV::open_read_only(&fname)?,
+        false => ActionKV::open(&fname)?,
+    };
+
+    if let Command::Fsck = command {
+        let bad = a.fsck()?;
+        for corruption in &bad {
+            println!("{}", corruption);
+        }
+        return Ok(match bad.is_empty() {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::FAILURE,
+        });
+    }
+
+    a.load_with(if recover {
+        Recovery::TruncateTail
+    } else {
+        Recovery::Strict
+    })?;
+
+    let decode = |arg: &std::ffi::OsStr| encoding.decode(arg.as_encoded_bytes());
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    match command {
+        Command::Get { key } => match a.get(&decode(&key)?)? {
+            Some(value) => {
+                out.write_all(&encoding.encode(&value)?)?;
+                if encoding != Encoding::Raw {
+                    out.write_all(b"\n")?;
+                }
+            }
+            None => {
+                eprintln!("akv: {:?} not found", key);
+                return Ok(ExitCode::from(NOT_FOUND));
+            }
+        },
+
+        Command::Insert { key, value, ttl } => {
+            let (key, value) = (decode(&key)?, decode(&value)?);
+            match ttl {
+                Some(ttl) => a.insert_with_ttl(&key, &value, ttl)?,
+                None => a.insert(&key, &value)?,
+            }
+            a.save_hint()?;
+        }
+
+        Command::Update { key, value } => {
+            a.update(&decode(&key)?, &decode(&value)?)?;
+            a.save_hint()?;
+        }
+
+        Command::Delete { key } => {
+            let decoded = decode(&key)?;
+            if a.get(&decoded)?.is_none() {
+                eprintln!("akv: {:?} not found", key);
+                return Ok(ExitCode::from(NOT_FOUND));
+            }
+            a.delete(&decoded)?;
+            a.save_hint()?;
+        }
+
+        Command::List { prefix } => {
+            let prefix = match prefix {
+                Some(prefix) => decode(&prefix)?,
+                None => Vec::new(),
+            };
+            for kv in a.scan_prefix(&prefix) {
+                out.write_all(&encoding.encode(&kv?.key)?)?;
+                out.write_all(b"\n")?;
+            }
+        }
+
+        Command::Scan { start, end } => {
+            let (start, end) = (decode(&start)?, decode(&end)?);
+            for kv in a.scan((
+                Bound::Included(start.as_slice()),
+                Bound::Excluded(end.as_slice()),
+            )) {
+                write_pair(&mut out, encoding, &kv?)?;
+            }
+        }
+
+        Command::Dump => {
+            for kv in a.scan(..) {
+                write_pair(&mut out, encoding, &kv?)?;
+            }
+        }
+
+        Command::Load => {
+            let stdin = io::stdin().lock();
+            for (n, line) in stdin.split(b'\n').enumerate() {
+                let mut line = line?;
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                if line.is_empty() {
+                    continue;
+                }
+
+                let Some(tab) = line.iter().position(|&byte| byte == b'\t') else {
+                    bail!("line {}: expected key<TAB>value", n + 1);
+                };
+                let key = encoding
+                    .decode(&line[..tab])
+                    .with_context(|| format!("line {}", n + 1))?;
+                let value = encoding
+                    .decode(&line[tab + 1..])
+                    .with_context(|| format!("line {}", n + 1))?;
+                a.insert(&key, &value)?;
+            }
+            a.save_hint()?;
+        }
+
+        Command::Export { format, output } => {
+            match output {
+                Some(path) => export::export(&a, format, File::create(path)?)?,
+                None => export::export(&a, format, &mut out)?,
+            };
+        }
+
+        Command::Import { format, input } => {
+            let imported = match input {
+                Some(path) => export::import(&mut a, format, BufReader::new(File::open(path)?)),
+                None => export::import(&mut a, format, io::stdin().lock()),
+            };
+            // keep the hint in step with whatever made it in before a bad row
+            a.save_hint()?;
+            eprintln!("imported {} pairs", imported?);
+        }
+
+        Command::Snapshot { dest } => {
+            let manifest = a.snapshot(&dest)?;
+            eprintln!(
+                "snapshot of {} keys, {} records",
+                manifest.keys, manifest.records
+            );
+        }
+
+        Command::Compact => a.compact()?,
+
+        Command::Stats => {
+            let stats = a.stats()?;
+            writeln!(out, "keys\t{}", stats.keys)?;
+            writeln!(out, "segments\t{}", stats.segments)?;
+            writeln!(out, "bytes\t{}", stats.bytes)?;
+            writeln!(out, "version\t{}", stats.version)?;
+        }
+
+        Command::Fsck | Command::Restore { .. } | Command::Tail { .. } => {
+            unreachable!("handled before the store is loaded")
+        }
+    }
+
+    out.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Writes `kv` as a `key<TAB>value` line.
+fn write_pair<W: Write>(out: &mut W, encoding: Encoding, kv: &KeyValuePair) -> Result<()> {
+    out.write_all(&encoding.encode(&kv.key)?)?;
+    out.write_all(b"\t")?;
+    out.write_all(&encoding.encode(&kv.value)?)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
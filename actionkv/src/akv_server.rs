@@ -0,0 +1,80 @@
+use anyhow::Result;
+use clap::Parser;
+use std::{thread, time::Duration};
+
+use libactionkv::{
+    ActionKV, Recovery, SharedKV,
+    replication::{Follower, Leader},
+    server::Server,
+};
+
+/// How long a follower waits before reconnecting to its leader.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Serves a store over the Redis protocol (GET, SET, DEL, EXISTS, SCAN)
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    fname: String,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:6380")]
+    listen: String,
+
+    /// Truncate a torn record at the end of FILE instead of failing to load
+    #[arg(long)]
+    recover: bool,
+
+    /// Also ship the log to followers that connect to this address
+    #[arg(long, value_name = "ADDR")]
+    replicate: Option<String>,
+
+    /// Copy the log of the leader at this address, and refuse writes
+    #[arg(long, value_name = "ADDR", conflicts_with = "replicate")]
+    follow: Option<String>,
+}
+
+fn main() -> Result<()> {
+    let Args {
+        fname,
+        listen,
+        recover,
+        replicate,
+        follow,
+    } = Args::parse();
+
+    let mut a = ActionKV::open(fname.as_ref())?;
+    a.load_with(if recover {
+        Recovery::TruncateTail
+    } else {
+        Recovery::Strict
+    })?;
+    let store = SharedKV::new(a)?;
+    // the next start only has to replay what this run writes
+    store.save_hint()?;
+
+    if let Some(addr) = replicate {
+        let leader = Leader::bind(&addr, store.clone())?;
+        eprintln!("shipping the log to followers on {}", leader.local_addr()?);
+        thread::spawn(move || leader.run());
+    }
+
+    if let Some(addr) = &follow {
+        let mut follower = Follower::new(store.clone())?;
+        let addr = addr.clone();
+        thread::spawn(move || {
+            loop {
+                if let Err(err) = follower.run(&addr) {
+                    eprintln!("leader {}: {}", addr, err);
+                }
+                thread::sleep(RECONNECT_DELAY);
+            }
+        });
+    }
+
+    let server = Server::bind(&listen, store)?.read_only(follow.is_some());
+    eprintln!("listening on {}", server.local_addr()?);
+    server.run()?;
+
+    Ok(())
+}
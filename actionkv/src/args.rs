@@ -1,10 +1,196 @@
-use clap::Parser;
+use std::{ffi::OsString, fmt, path::PathBuf, time::Duration};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::{Position, export::Format};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
-    pub fname: String,
-    pub action: String,
-    pub key: String,
-    pub maybe_value: Option<String>,
+    /// Store to open: a store directory, or a single log file
+    pub fname: PathBuf,
+
+    /// How keys and values are written on the command line and printed
+    #[arg(long, short, value_enum, default_value_t = Encoding::Utf8, global = true)]
+    pub encoding: Encoding,
+
+    /// Truncate a torn record at the end of FILE instead of failing to load
+    #[arg(long, global = true)]
+    pub recover: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the value of KEY; exits with 1 if there is none
+    Get { key: OsString },
+    /// Set KEY to VALUE
+    Insert {
+        key: OsString,
+        value: OsString,
+        /// Forget the key after this many seconds
+        #[arg(long, value_parser = parse_seconds)]
+        ttl: Option<Duration>,
+    },
+    /// Same as insert
+    Update { key: OsString, value: OsString },
+    /// Remove KEY; exits with 1 if there was none
+    Delete { key: OsString },
+    /// Print every key that starts with PREFIX, one per line
+    List { prefix: Option<OsString> },
+    /// Print the pairs with START <= key < END as `key<TAB>value` lines
+    Scan { start: OsString, end: OsString },
+    /// Print every pair as a `key<TAB>value` line
+    ///
+    /// Use `--encoding hex` or `base64` if keys or values may contain tabs or
+    /// newlines.
+    Dump,
+    /// Insert the `key<TAB>value` lines read from standard input
+    Load,
+    /// Write every pair as JSON Lines or CSV, base64-encoding binary data
+    Export {
+        #[arg(long, value_enum, default_value = "jsonl")]
+        format: Format,
+        /// File to write to instead of standard output
+        output: Option<PathBuf>,
+    },
+    /// Insert the pairs from a file written by export
+    Import {
+        #[arg(long, value_enum, default_value = "jsonl")]
+        format: Format,
+        /// File to read instead of standard input
+        input: Option<PathBuf>,
+    },
+    /// Copy the store into the new directory DEST, with a manifest to check
+    /// it against
+    Snapshot { dest: PathBuf },
+    /// Recreate FILE from the snapshot at SNAPSHOT after checking it; FILE
+    /// must not exist or be an empty directory
+    Restore { snapshot: PathBuf },
+    /// Print puts and deletes as they are appended to the log, as
+    /// `SEGMENT:OFFSET<TAB>put<TAB>key<TAB>value` or
+    /// `SEGMENT:OFFSET<TAB>delete<TAB>key` lines
+    Tail {
+        /// Start at SEGMENT:OFFSET, as printed by an earlier tail, instead
+        /// of at the end of the log
+        #[arg(long, value_parser = parse_position)]
+        from: Option<Position>,
+    },
+    /// Rewrite the log without superseded, deleted or expired records
+    Compact,
+    /// Check every record and print the bad ones; exits with 1 if any are found
+    Fsck,
+    /// Print the number of keys, segments and bytes on disk
+    Stats,
+}
+
+/// How keys and values are turned into text and back.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8 text; printing bytes that are not valid UTF-8 is an error
+    Utf8,
+    /// Lower-case hexadecimal
+    Hex,
+    /// Standard base64 with padding
+    Base64,
+    /// Bytes as given; `get` prints the value without a trailing newline
+    Raw,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Input that does not decode under the chosen [`Encoding`].
+#[derive(Debug)]
+pub struct DecodeError {
+    encoding: Encoding,
+    input: Vec<u8>,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} is not valid {}",
+            String::from_utf8_lossy(&self.input),
+            self.encoding
+        )
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Bytes that cannot be printed under the chosen [`Encoding`].
+#[derive(Debug)]
+pub struct EncodeError {
+    encoding: Encoding,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "data is not valid {}, try --encoding hex or base64",
+            self.encoding
+        )
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl Encoding {
+    pub fn decode(self, input: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let error = || DecodeError {
+            encoding: self,
+            input: input.to_vec(),
+        };
+
+        match self {
+            Encoding::Utf8 => match std::str::from_utf8(input) {
+                Ok(text) => Ok(text.as_bytes().to_vec()),
+                Err(_) => Err(error()),
+            },
+            Encoding::Hex => hex::decode(input).map_err(|_| error()),
+            Encoding::Base64 => BASE64.decode(input).map_err(|_| error()),
+            Encoding::Raw => Ok(input.to_vec()),
+        }
+    }
+
+    pub fn encode(self, bytes: &[u8]) -> Result<Vec<u8>, EncodeError> {
+        match self {
+            Encoding::Utf8 => match std::str::from_utf8(bytes) {
+                Ok(text) => Ok(text.as_bytes().to_vec()),
+                Err(_) => Err(EncodeError { encoding: self }),
+            },
+            Encoding::Hex => Ok(hex::encode(bytes).into_bytes()),
+            Encoding::Base64 => Ok(BASE64.encode(bytes).into_bytes()),
+            Encoding::Raw => Ok(bytes.to_vec()),
+        }
+    }
+}
+
+fn parse_position(arg: &str) -> Result<Position, String> {
+    let (segment, offset) = arg
+        .split_once(':')
+        .ok_or_else(|| "expected SEGMENT:OFFSET".to_string())?;
+
+    Ok(Position {
+        segment: segment.parse().map_err(|err| format!("segment: {}", err))?,
+        offset: offset.parse().map_err(|err| format!("offset: {}", err))?,
+    })
+}
+
+fn parse_seconds(arg: &str) -> Result<Duration, String> {
+    arg.parse::<u64>()
+        .map(Duration::from_secs)
+        .map_err(|err| format!("{}: expected a whole number of seconds", err))
 }
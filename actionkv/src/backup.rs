@@ -0,0 +1,247 @@
+//! Point-in-time copies of a store.
+//!
+//! A snapshot is a store directory holding the segments as they were up to
+//! a recorded position, a hint file for that position, and a
+//! [`MANIFEST_FILE`] listing every other file with its size and checksum.
+//! Records appended after the position are left out, so the log can keep
+//! growing while it is copied.
+//!
+//! ```text
+//! snapshot/
+//!   00000000.akv
+//!   00000001.akv
+//!   index.hint
+//!   MANIFEST.json
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crc::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ActionKV, ByteString, CKSUM, FORMAT_VERSION, HINT_FILE, Position, RecordKind, hint,
+    segment::{self, Segments},
+    sync_dir,
+};
+
+/// Name of the manifest inside a snapshot directory.
+pub const MANIFEST_FILE: &str = "MANIFEST.json";
+
+/// What a snapshot holds, as written to its [`MANIFEST_FILE`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Format version of the build that took the snapshot.
+    pub format_version: u32,
+    /// Where the log ended when the snapshot was taken.
+    pub covered: Position,
+    /// Put and delete records in the copied segments.
+    pub records: u64,
+    /// Keys in the copied index.
+    pub keys: usize,
+    pub files: Vec<FileEntry>,
+}
+
+/// A file in a snapshot, checked as a whole by [`verify`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub name: String,
+    pub bytes: u64,
+    /// CRC-32/CKSUM of the whole file, the checksum records use.
+    pub checksum: u32,
+}
+
+/// Copies `segments` up to `covered`, with `index` as the hint, into the new
+/// snapshot directory `dest`.
+pub(crate) fn write(
+    dest: &Path,
+    segments: &Segments,
+    covered: Position,
+    index: &BTreeMap<ByteString, Position>,
+) -> io::Result<Manifest> {
+    create_empty_dir(dest)?;
+
+    let mut files = Vec::new();
+    for segment in segments.values().filter(|s| s.id <= covered.segment) {
+        let len = match segment.id == covered.segment {
+            true => covered.offset,
+            false => segment.len()?,
+        };
+        let path = segment::path_in(dest, segment.id);
+        let mut from = segment.reader_at(0)?.take(len);
+        files.push(copy(&mut from, &path)?);
+    }
+
+    let hint_path = dest.join(HINT_FILE);
+    hint::write(&hint_path, covered, index)?;
+    files.push(describe(&hint_path)?);
+
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        covered,
+        records: count_records(dest)?,
+        keys: index.len(),
+        files,
+    };
+
+    let mut f = BufWriter::new(File::create(dest.join(MANIFEST_FILE))?);
+    serde_json::to_writer_pretty(&mut f, &manifest)?;
+    f.write_all(b"\n")?;
+    f.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    sync_dir(dest)?;
+
+    Ok(manifest)
+}
+
+/// Reads the manifest of the snapshot at `snapshot` and checks every file
+/// and the record count against it.
+pub fn verify(snapshot: &Path) -> io::Result<Manifest> {
+    let manifest: Manifest = serde_json::from_reader(File::open(snapshot.join(MANIFEST_FILE))?)?;
+
+    for entry in &manifest.files {
+        // names come from the manifest, and restore writes to them
+        if Path::new(&entry.name).file_name() != Some(entry.name.as_ref()) {
+            return Err(invalid_data(format!("bad file name {:?}", entry.name)));
+        }
+        check(entry, &describe(&snapshot.join(&entry.name))?)?;
+    }
+
+    let records = count_records(snapshot)?;
+    if records != manifest.records {
+        return Err(invalid_data(format!(
+            "snapshot holds {} records, manifest says {}",
+            records, manifest.records
+        )));
+    }
+
+    Ok(manifest)
+}
+
+/// Verifies the snapshot at `snapshot` and copies it into `dest`, which must
+/// not exist or be empty. The result is a store directory that can be opened
+/// with [`ActionKV::open`].
+pub fn restore(snapshot: &Path, dest: &Path) -> io::Result<Manifest> {
+    let manifest = verify(snapshot)?;
+    create_empty_dir(dest)?;
+
+    for entry in &manifest.files {
+        let mut from = File::open(snapshot.join(&entry.name))?;
+        let copied = copy(&mut from, &dest.join(&entry.name))?;
+        check(entry, &copied)?;
+    }
+    sync_dir(dest)?;
+
+    Ok(manifest)
+}
+
+/// Creates `dir`, or checks that it is an empty directory.
+fn create_empty_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    if fs::read_dir(dir)?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} is not empty", dir.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Copies `from` into a new file at `to` and describes what was written.
+fn copy<R: Read>(from: &mut R, to: &Path) -> io::Result<FileEntry> {
+    let mut f = Checksummed::new(BufWriter::new(File::create(to)?));
+    io::copy(from, &mut f)?;
+    f.inner.flush()?;
+    f.inner.get_ref().sync_all()?;
+
+    Ok(f.finish(to))
+}
+
+/// Describes the file at `path`.
+fn describe(path: &Path) -> io::Result<FileEntry> {
+    let mut f = Checksummed::new(io::sink());
+    io::copy(&mut File::open(path)?, &mut f)?;
+
+    Ok(f.finish(path))
+}
+
+fn check(expected: &FileEntry, found: &FileEntry) -> io::Result<()> {
+    if found.bytes != expected.bytes || found.checksum != expected.checksum {
+        return Err(invalid_data(format!(
+            "{}: {} bytes with checksum {:#010x}, manifest says {} bytes with checksum {:#010x}",
+            expected.name, found.bytes, found.checksum, expected.bytes, expected.checksum
+        )));
+    }
+
+    Ok(())
+}
+
+/// Counts the committed put and delete records in the store directory `dir`.
+fn count_records(dir: &Path) -> io::Result<u64> {
+    let mut records = 0;
+
+    for segment in segment::open_dir_read(dir)?.values() {
+        let unfinished = ActionKV::replay_records(segment, segment.data_start(), |_, record| {
+            if matches!(record.kind, RecordKind::Put | RecordKind::Delete) {
+                records += 1;
+            }
+        })?;
+        if let Some(offset) = unfinished {
+            return Err(invalid_data(format!(
+                "segment {} ends in an unfinished batch at offset {}",
+                segment.id, offset
+            )));
+        }
+    }
+
+    Ok(records)
+}
+
+/// A writer that checksums and counts what passes through it.
+struct Checksummed<W> {
+    inner: W,
+    digest: Digest<'static, u32>,
+    bytes: u64,
+}
+
+impl<W: Write> Checksummed<W> {
+    fn new(inner: W) -> Self {
+        Checksummed {
+            inner,
+            digest: CKSUM.digest(),
+            bytes: 0,
+        }
+    }
+
+    /// Describes what was written as the file at `path`.
+    fn finish(self, path: &Path) -> FileEntry {
+        let name = path.file_name().unwrap_or_default();
+        FileEntry {
+            name: name.to_string_lossy().into_owned(),
+            bytes: self.bytes,
+            checksum: self.digest.finalize(),
+        }
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.digest.update(&buf[..written]);
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
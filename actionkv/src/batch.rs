@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use crate::{ByteStr, ByteString, Position, RecordKind, changes::Subscribers};
+
+/// A group of writes that [`ActionKV::write`](crate::ActionKV::write) applies
+/// all-or-nothing.
+///
+/// On disk the writes are framed by a `BatchBegin` and a `BatchCommit`
+/// record. [`ActionKV::load`](crate::ActionKV::load) only applies a batch
+/// once it has seen its commit marker, so a crash part-way through writing
+/// one leaves none of it visible.
+///
+/// ```no_run
+/// # use libactionkv::{ActionKV, WriteBatch};
+/// # let mut store = ActionKV::open("store".as_ref())?;
+/// # store.load()?;
+/// let mut batch = WriteBatch::new();
+/// batch.put(b"from", b"90").put(b"to", b"110").delete(b"pending");
+/// store.write(batch)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct WriteBatch {
+    pub(crate) ops: Vec<(RecordKind, ByteString, ByteString)>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, key: &ByteStr, value: &ByteStr) -> &mut Self {
+        self.ops
+            .push((RecordKind::Put, key.to_vec(), value.to_vec()));
+        self
+    }
+
+    pub fn delete(&mut self, key: &ByteStr) -> &mut Self {
+        self.ops
+            .push((RecordKind::Delete, key.to_vec(), Vec::new()));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Updates `index` once the batch's records have been written at
+    /// `positions`, and tells `subscribers`.
+    pub(crate) fn apply(
+        self,
+        positions: Vec<Position>,
+        index: &mut BTreeMap<ByteString, Position>,
+        subscribers: &mut Subscribers,
+    ) {
+        for ((kind, key, _), position) in self.ops.into_iter().zip(positions) {
+            let old = match kind {
+                RecordKind::Put => index.insert(key.clone(), position),
+                _ => index.remove(&key),
+            };
+            subscribers.notify(kind, &key, old, position);
+        }
+    }
+}
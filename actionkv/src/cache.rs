@@ -0,0 +1,199 @@
+//! A byte-bounded, least-recently-used cache of values read by
+//! [`ActionKV::get`](crate::ActionKV::get).
+//!
+//! Each entry remembers the position it was read from, so an entry is only
+//! ever used for the record the index currently points at, even if the index
+//! was changed behind the store's back.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{ByteStr, ByteString, Position};
+
+/// Bytes charged for each entry on top of its key and value.
+const ENTRY_OVERHEAD: usize = 64;
+
+#[derive(Debug)]
+struct Entry {
+    position: Position,
+    value: ByteString,
+    expires_at: Option<u64>,
+    /// When the entry was last used; the smallest is evicted first.
+    tick: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Cache {
+    capacity: usize,
+    used: usize,
+    tick: u64,
+    entries: HashMap<ByteString, Entry>,
+    /// Keys by the tick they were last used at.
+    recency: BTreeMap<u64, ByteString>,
+    hits: u64,
+    misses: u64,
+}
+
+/// A cached value: what the record at `position` holds.
+pub(crate) struct Cached {
+    pub value: ByteString,
+    pub expires_at: Option<u64>,
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            capacity,
+            ..Cache::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Bytes currently charged against the capacity.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Changes the capacity, evicting entries until they fit.
+    pub fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Returns the value cached for `key` if it was read from `position`,
+    /// counting a hit or a miss.
+    pub fn get(&mut self, key: &ByteStr, position: Position) -> Option<Cached> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = match self.entries.get_mut(key) {
+            Some(entry) if entry.position == position => entry,
+            _ => {
+                self.misses += 1;
+                return None;
+            }
+        };
+
+        self.recency.remove(&entry.tick);
+        entry.tick = tick;
+        self.recency.insert(tick, key.to_vec());
+        self.hits += 1;
+
+        Some(Cached {
+            value: entry.value.clone(),
+            expires_at: entry.expires_at,
+        })
+    }
+
+    /// Remembers that the record for `key` at `position` holds `value`.
+    pub fn insert(
+        &mut self,
+        key: &ByteStr,
+        position: Position,
+        value: &ByteStr,
+        expires_at: Option<u64>,
+    ) {
+        let size = charge(key, value);
+        if size > self.capacity {
+            return;
+        }
+
+        self.remove(key);
+        self.tick += 1;
+        self.entries.insert(
+            key.to_vec(),
+            Entry {
+                position,
+                value: value.to_vec(),
+                expires_at,
+                tick: self.tick,
+            },
+        );
+        self.recency.insert(self.tick, key.to_vec());
+        self.used += size;
+        self.evict();
+    }
+
+    /// Forgets whatever is cached for `key`.
+    pub fn remove(&mut self, key: &ByteStr) {
+        if let Some((key, entry)) = self.entries.remove_entry(key) {
+            self.recency.remove(&entry.tick);
+            self.used -= charge(&key, &entry.value);
+        }
+    }
+
+    /// Forgets everything, keeping the counters.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.used = 0;
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.capacity {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.used -= charge(&key, &entry.value);
+            }
+        }
+    }
+}
+
+fn charge(key: &ByteStr, value: &ByteStr) -> usize {
+    key.len() + value.len() + ENTRY_OVERHEAD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(offset: u64) -> Position {
+        Position { segment: 0, offset }
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = Cache::new(3 * charge(b"k1", b"v1"));
+        cache.insert(b"k1", at(1), b"v1", None);
+        cache.insert(b"k2", at(2), b"v2", None);
+        cache.insert(b"k3", at(3), b"v3", None);
+        assert!(cache.get(b"k1", at(1)).is_some());
+
+        cache.insert(b"k4", at(4), b"v4", None);
+        assert!(cache.get(b"k2", at(2)).is_none());
+        assert!(cache.get(b"k1", at(1)).is_some());
+        assert!(cache.get(b"k3", at(3)).is_some());
+        assert!(cache.get(b"k4", at(4)).is_some());
+        assert_eq!(cache.used(), 3 * charge(b"k1", b"v1"));
+        assert_eq!((cache.hits(), cache.misses()), (4, 1));
+    }
+
+    #[test]
+    fn test_ignores_stale_positions_and_oversized_values() {
+        let mut cache = Cache::new(100);
+        cache.insert(b"k", at(1), b"v", None);
+        assert!(cache.get(b"k", at(2)).is_none());
+
+        cache.insert(b"big", at(3), &[0; 100], None);
+        assert!(cache.get(b"big", at(3)).is_none());
+        assert!(cache.get(b"k", at(1)).is_some());
+
+        cache.remove(b"k");
+        assert_eq!(cache.used(), 0);
+    }
+}
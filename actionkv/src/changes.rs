@@ -0,0 +1,326 @@
+//! Finding out about writes as they happen.
+//!
+//! There are two ways in. [`ActionKV::subscribe`](crate::ActionKV::subscribe)
+//! hands out a channel of [`Change`]s made through that store, in the same
+//! process. A [`Tail`] instead follows the log on disk from a given position,
+//! like `XREAD` on a stream, so it also sees writes made by other processes
+//! and can pick up where it left off.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, Read, Seek},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ActionKV, ByteStr, ByteString, Position, Record, RecordKind,
+    segment::{self, Segment},
+};
+
+/// How long a [`Tail`] waits before looking at the log again.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A key that was written or deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// [`RecordKind::Put`] or [`RecordKind::Delete`].
+    pub kind: RecordKind,
+    pub key: ByteString,
+    /// The record the key pointed at before, if it was in the index.
+    pub old: Option<Position>,
+    /// The record that was just written.
+    pub new: Position,
+}
+
+/// The senders of every live subscription, by the prefix they asked for.
+#[derive(Debug, Default)]
+pub(crate) struct Subscribers {
+    senders: Vec<(ByteString, Sender<Change>)>,
+}
+
+impl Subscribers {
+    pub fn subscribe(&mut self, prefix: &ByteStr) -> Receiver<Change> {
+        let (tx, rx) = mpsc::channel();
+        self.senders.push((prefix.to_vec(), tx));
+        rx
+    }
+
+    /// Tells everyone watching `key` that the index entry for it moved from
+    /// `old` to `new`, and forgets subscribers that have hung up.
+    pub fn notify(
+        &mut self,
+        kind: RecordKind,
+        key: &ByteStr,
+        old: Option<Position>,
+        new: Position,
+    ) {
+        self.senders.retain(|(prefix, tx)| {
+            if !key.starts_with(prefix) {
+                return true;
+            }
+            let change = Change {
+                kind,
+                key: key.to_vec(),
+                old,
+                new,
+            };
+            tx.send(change).is_ok()
+        });
+    }
+}
+
+/// A single record, or every record of a committed batch, as read by a
+/// [`Tail`].
+#[derive(Debug)]
+pub(crate) struct Unit {
+    /// Where the record, or the batch's begin marker, starts.
+    pub start: Position,
+    /// Where the unit ends in the same segment.
+    pub end: u64,
+    /// Format version of the segment, needed to decode the raw bytes.
+    pub version: u32,
+    pub records: Vec<(Position, Record)>,
+}
+
+/// Follows a store's log on disk, yielding each put and delete record once
+/// it is complete, and the records of a batch once its commit marker is.
+///
+/// Iterating blocks until the next record is written. A record that is
+/// only partly on disk is taken to be a write in progress, so a log whose
+/// active segment ends in a torn record stalls the tail until the store is
+/// recovered. Compaction rewrites segments under new offsets, so a tail
+/// should be reopened from a fresh position after the store is compacted.
+#[derive(Debug)]
+pub struct Tail {
+    /// The store directory, or `None` for a single-file store.
+    dir: Option<PathBuf>,
+    segment: Segment,
+    offset: u64,
+    /// Records already read but not yet handed out.
+    pending: VecDeque<(Position, Record)>,
+}
+
+impl Tail {
+    pub(crate) fn open(path: &Path, segmented: bool, from: Position) -> io::Result<Self> {
+        let (dir, file) = match segmented {
+            true => (
+                Some(path.to_path_buf()),
+                segment::path_in(path, from.segment),
+            ),
+            false => (None, path.to_path_buf()),
+        };
+        if !file.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("segment {} does not exist", from.segment),
+            ));
+        }
+
+        let segment = Segment::open_read(from.segment, &file)?;
+        let offset = from.offset.max(segment.data_start());
+
+        Ok(Tail {
+            dir,
+            segment,
+            offset,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Follows the store at `path` from `from`, or from the end of the log
+    /// if that is `None`, without opening the store itself. Unlike
+    /// [`ActionKV::tail`] this only needs read access to the store.
+    pub fn follow(path: &Path, from: Option<Position>) -> io::Result<Self> {
+        let segmented = !path.is_file();
+        let from = match from {
+            Some(from) => from,
+            None => end_of_log(path, segmented)?,
+        };
+
+        Tail::open(path, segmented, from)
+    }
+
+    /// Where the next record handed out starts, to resume from later.
+    pub fn position(&self) -> Position {
+        match self.pending.front() {
+            Some((position, _)) => *position,
+            None => Position {
+                segment: self.segment.id,
+                offset: self.offset,
+            },
+        }
+    }
+
+    /// The next record, or `None` if there is none yet.
+    pub fn try_next(&mut self) -> io::Result<Option<(Position, Record)>> {
+        if self.pending.is_empty()
+            && let Some(unit) = self.next_unit()?
+        {
+            self.pending.extend(unit.records);
+        }
+
+        Ok(self.pending.pop_front())
+    }
+
+    /// The next record, waiting up to `timeout` for it to be written.
+    pub fn next_timeout(&mut self, timeout: Duration) -> io::Result<Option<(Position, Record)>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(next) = self.try_next()? {
+                return Ok(Some(next));
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// The next complete record or batch holding at least one put or delete,
+    /// moving on to the next segment once this one is done. Must only be
+    /// called once every record already read has been handed out.
+    pub(crate) fn next_unit(&mut self) -> io::Result<Option<Unit>> {
+        debug_assert!(self.pending.is_empty());
+
+        loop {
+            let len = self.segment.len()?;
+            if self.offset < len {
+                let result = self.read_unit(len);
+                if let Ok(Some(unit)) = result {
+                    self.offset = unit.end;
+                    match unit.records.is_empty() {
+                        true => continue,
+                        false => return Ok(Some(unit)),
+                    }
+                }
+
+                // the writer may still be finishing the record, unless it
+                // has already moved on to the next segment
+                if self.next_segment()?.is_none() || self.segment.len()? != len {
+                    return Ok(None);
+                }
+                return Err(result.err().unwrap_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("segment {} ends in an unfinished batch", self.segment.id),
+                    )
+                }));
+            }
+
+            match self.next_segment()? {
+                Some(next) => {
+                    self.offset = next.data_start();
+                    self.segment = next;
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The bytes `unit` was read from, exactly as they are in the log. Only
+    /// valid until the next call to [`Tail::next_unit`].
+    pub(crate) fn raw(&self, unit: &Unit) -> io::Result<Vec<u8>> {
+        let mut raw = Vec::with_capacity((unit.end - unit.start.offset) as usize);
+        self.segment
+            .reader_at(unit.start.offset)?
+            .take(unit.end - unit.start.offset)
+            .read_to_end(&mut raw)?;
+
+        Ok(raw)
+    }
+
+    /// Reads one record, or a whole batch, from `offset`. Returns `None` if
+    /// a batch runs past `len`.
+    fn read_unit(&self, len: u64) -> io::Result<Option<Unit>> {
+        let segment = &self.segment;
+        let mut f = segment.reader_at(self.offset)?;
+        let mut unit = Unit {
+            start: Position {
+                segment: segment.id,
+                offset: self.offset,
+            },
+            end: self.offset,
+            version: segment.version,
+            records: Vec::new(),
+        };
+        let mut in_batch = false;
+
+        loop {
+            let position = Position {
+                segment: segment.id,
+                offset: f.stream_position()?,
+            };
+            if position.offset >= len {
+                return Ok(None);
+            }
+
+            let record = ActionKV::process_record(&mut f, segment.version, position)?;
+            match record.kind {
+                RecordKind::BatchBegin => in_batch = true,
+                // a commit without a begin has nothing to apply
+                RecordKind::BatchCommit => break,
+                _ => {
+                    unit.records.push((position, record));
+                    if !in_batch {
+                        break;
+                    }
+                }
+            }
+        }
+
+        unit.end = f.stream_position()?;
+        Ok(Some(unit))
+    }
+
+    /// The segment after this one, if there is one yet.
+    fn next_segment(&self) -> io::Result<Option<Segment>> {
+        let Some(dir) = &self.dir else {
+            return Ok(None);
+        };
+
+        let paths = segment::paths_in(dir)?;
+        match paths.range(self.segment.id + 1..).next() {
+            Some((id, path)) => Segment::open_read(*id, path).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Position just past the last record of the store at `path`.
+fn end_of_log(path: &Path, segmented: bool) -> io::Result<Position> {
+    let (id, file) = match segmented {
+        true => segment::paths_in(path)?.pop_last().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} holds no segments", path.display()),
+            )
+        })?,
+        false => (0, path.to_path_buf()),
+    };
+
+    Ok(Position {
+        segment: id,
+        offset: fs::metadata(file)?.len(),
+    })
+}
+
+impl Iterator for Tail {
+    type Item = io::Result<(Position, Record)>;
+
+    /// Blocks until the next record is written.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.try_next() {
+                Ok(Some(next)) => return Some(Ok(next)),
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the current time used to decide whether a key has expired.
+///
+/// The store uses [`SystemClock`] unless told otherwise with
+/// [`ActionKV::set_clock`](crate::ActionKV::set_clock); tests can plug in a
+/// clock they move by hand to make expiry deterministic.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The wall clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Milliseconds since the Unix epoch, the unit expiry times are stored in.
+pub(crate) fn millis(clock: &dyn Clock) -> u64 {
+    let since_epoch = clock
+        .now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO);
+
+    since_epoch.as_millis().try_into().unwrap_or(u64::MAX)
+}
@@ -0,0 +1,93 @@
+//! Per-record value compression.
+//!
+//! A compressed record has the codec's flag set in its kind byte and stores
+//! the compressed value in place of the plain one. The checksum covers the
+//! stored bytes, so a log can be checked without decompressing anything.
+
+use std::{borrow::Cow, io};
+
+/// Flag in the kind byte of a record whose value is LZ4-compressed.
+const LZ4: u8 = 0x10;
+
+/// Flag in the kind byte of a record whose value is zstd-compressed.
+const ZSTD: u8 = 0x20;
+
+/// Bits of the kind byte that name the codec.
+pub(crate) const FLAGS: u8 = LZ4 | ZSTD;
+
+/// Values shorter than this are always stored as they are.
+const MIN_LEN: usize = 64;
+
+/// How [`ActionKV`](crate::ActionKV) compresses the values it writes, set with
+/// [`ActionKV::set_compression`](crate::ActionKV::set_compression).
+///
+/// Whatever the setting, small values and values that would not shrink are
+/// stored uncompressed, and records written with any codec this build
+/// supports can be read back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    #[cfg(feature = "lz4")]
+    Lz4,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// Returns the flag to store with `value` and the bytes to store for it.
+pub(crate) fn compress(compression: Compression, value: &[u8]) -> io::Result<(u8, Cow<'_, [u8]>)> {
+    if value.len() < MIN_LEN {
+        return Ok((0, Cow::Borrowed(value)));
+    }
+
+    let compressed: Option<(u8, Vec<u8>)> = match compression {
+        Compression::None => None,
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => Some((LZ4, lz4_flex::compress_prepend_size(value))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Some((
+            ZSTD,
+            zstd::encode_all(value, zstd::DEFAULT_COMPRESSION_LEVEL)?,
+        )),
+    };
+
+    match compressed {
+        Some((flag, compressed)) if compressed.len() < value.len() => {
+            Ok((flag, Cow::Owned(compressed)))
+        }
+        _ => Ok((0, Cow::Borrowed(value))),
+    }
+}
+
+/// Turns the `stored` bytes of a record with `flags` back into its value.
+pub(crate) fn decompress(flags: u8, stored: Vec<u8>) -> io::Result<Vec<u8>> {
+    match flags & FLAGS {
+        0 => Ok(stored),
+
+        #[cfg(feature = "lz4")]
+        LZ4 => lz4_flex::decompress_size_prepended(&stored)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        #[cfg(not(feature = "lz4"))]
+        LZ4 => Err(unsupported("lz4")),
+
+        #[cfg(feature = "zstd")]
+        ZSTD => zstd::decode_all(stored.as_slice()),
+        #[cfg(not(feature = "zstd"))]
+        ZSTD => Err(unsupported("zstd")),
+
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown compression flags {:#04x}", flags & FLAGS),
+        )),
+    }
+}
+
+fn unsupported(codec: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "record is {}-compressed, but actionkv was built without the {0} feature",
+            codec
+        ),
+    )
+}
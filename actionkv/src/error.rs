@@ -0,0 +1,59 @@
+use std::{error, fmt, io};
+
+/// A log record that could not be read back intact.
+///
+/// Returned inside an [`io::Error`] of kind [`io::ErrorKind::InvalidData`];
+/// use [`Corruption::from_io`] to get at it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    /// Segment holding the bad record; always `0` for a single-file store.
+    pub segment: u32,
+    /// Offset of the start of the bad record in its segment.
+    pub offset: u64,
+    /// Key length from the record header, `0` if the header itself was cut short.
+    pub key_len: u32,
+    /// Value length from the record header, `0` if the header itself was cut short.
+    pub val_len: u32,
+    /// Checksum stored in the record header.
+    pub saved_checksum: u32,
+    /// Checksum of the bytes on disk, `None` if the record was cut short.
+    pub checksum: Option<u32>,
+}
+
+impl Corruption {
+    /// Returns the corruption carried by `err`, if any.
+    pub fn from_io(err: &io::Error) -> Option<&Corruption> {
+        err.get_ref()?.downcast_ref::<Corruption>()
+    }
+
+    /// `true` if the log ends part-way through this record, as happens when a
+    /// write is interrupted by a crash.
+    pub fn is_torn(&self) -> bool {
+        self.checksum.is_none()
+    }
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.checksum {
+            Some(checksum) => write!(
+                f,
+                "data corruption in segment {} at offset {} (key_len {}, checksum {:08x} != {:08x})",
+                self.segment, self.offset, self.key_len, checksum, self.saved_checksum
+            ),
+            None => write!(
+                f,
+                "torn record in segment {} at offset {} (key_len {}, checksum {:08x})",
+                self.segment, self.offset, self.key_len, self.saved_checksum
+            ),
+        }
+    }
+}
+
+impl error::Error for Corruption {}
+
+impl From<Corruption> for io::Error {
+    fn from(corruption: Corruption) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, corruption)
+    }
+}
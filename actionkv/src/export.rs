@@ -0,0 +1,152 @@
+//! Moving key-value pairs in and out of a store as JSON Lines or CSV.
+//!
+//! Each pair becomes one row with `key`, `value` and `encoding` fields.
+//! When both key and value are valid UTF-8 they are written as text and
+//! `encoding` is `utf8`; otherwise both are base64 and `encoding` is
+//! `base64`. On import a missing `encoding` means `utf8`.
+//!
+//! ```text
+//! {"key":"greeting","value":"hello","encoding":"utf8"}
+//! {"key":"AP8=","value":"Cg0=","encoding":"base64"}
+//! ```
+
+use std::{
+    io::{self, BufRead, Write},
+    string::FromUtf8Error,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+
+use crate::{ActionKV, KeyValuePair};
+
+/// File formats for [`export`] and [`import`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// One JSON object per line
+    Jsonl,
+    /// A header row, then one `key,value,encoding` row per pair
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+/// A [`KeyValuePair`] as it appears in an exported file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Row {
+    key: String,
+    value: String,
+    #[serde(default)]
+    encoding: Encoding,
+}
+
+impl From<KeyValuePair> for Row {
+    fn from(kv: KeyValuePair) -> Self {
+        match (String::from_utf8(kv.key), String::from_utf8(kv.value)) {
+            (Ok(key), Ok(value)) => Row {
+                key,
+                value,
+                encoding: Encoding::Utf8,
+            },
+            (key, value) => {
+                let bytes = |text: Result<String, FromUtf8Error>| match text {
+                    Ok(text) => text.into_bytes(),
+                    Err(err) => err.into_bytes(),
+                };
+                Row {
+                    key: BASE64.encode(bytes(key)),
+                    value: BASE64.encode(bytes(value)),
+                    encoding: Encoding::Base64,
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<Row> for KeyValuePair {
+    type Error = io::Error;
+
+    fn try_from(row: Row) -> io::Result<Self> {
+        match row.encoding {
+            Encoding::Utf8 => Ok(KeyValuePair {
+                key: row.key.into_bytes(),
+                value: row.value.into_bytes(),
+            }),
+            Encoding::Base64 => {
+                let decode = |text: &str| BASE64.decode(text).map_err(invalid_data);
+                Ok(KeyValuePair {
+                    key: decode(&row.key)?,
+                    value: decode(&row.value)?,
+                })
+            }
+        }
+    }
+}
+
+/// Writes every live pair in `store` to `w`, in key order, and returns how
+/// many were written.
+pub fn export<W: Write>(store: &ActionKV, format: Format, w: W) -> io::Result<usize> {
+    let mut exported = 0;
+
+    match format {
+        Format::Jsonl => {
+            let mut w = io::BufWriter::new(w);
+            for kv in store.scan(..) {
+                serde_json::to_writer(&mut w, &Row::from(kv?))?;
+                w.write_all(b"\n")?;
+                exported += 1;
+            }
+            w.flush()?;
+        }
+        Format::Csv => {
+            let mut w = csv::Writer::from_writer(w);
+            for kv in store.scan(..) {
+                w.serialize(Row::from(kv?)).map_err(csv_error)?;
+                exported += 1;
+            }
+            w.flush()?;
+        }
+    }
+
+    Ok(exported)
+}
+
+/// Inserts every pair read from `r` into `store` and returns how many were
+/// inserted. Pairs read before a malformed row are kept.
+pub fn import<R: BufRead>(store: &mut ActionKV, format: Format, r: R) -> io::Result<usize> {
+    match format {
+        Format::Jsonl => {
+            let rows = serde_json::Deserializer::from_reader(r).into_iter::<Row>();
+            store.insert_all(rows.map(|row| KeyValuePair::try_from(row?)))
+        }
+        Format::Csv => {
+            let mut r = csv::Reader::from_reader(r);
+            let rows = r.deserialize::<Row>();
+            store.insert_all(rows.map(|row| KeyValuePair::try_from(row.map_err(csv_error)?)))
+        }
+    }
+}
+
+fn csv_error(err: csv::Error) -> io::Error {
+    if !err.is_io_error() {
+        return invalid_data(err);
+    }
+
+    match err.into_kind() {
+        csv::ErrorKind::Io(err) => err,
+        kind => invalid_data(format!("{:?}", kind)),
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
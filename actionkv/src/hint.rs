@@ -0,0 +1,117 @@
+//! Side-car index ("hint") file, in the spirit of Bitcask.
+//!
+//! A hint file is a snapshot of `ActionKV.index` together with the log
+//! position it is valid up to. Opening a store with a good hint only has to
+//! replay the records written after that position.
+//!
+//! Layout: `MAGIC | version | covered segment | covered offset |
+//! bincode(index) | checksum`, where the
+//! checksum covers every byte before it. A hint that is missing, damaged or
+//! ahead of the log is ignored, since the log can always be replayed.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use crate::{ByteString, CKSUM, Position, segment::Segments};
+
+const MAGIC: [u8; 4] = *b"\x89AKH";
+const VERSION: u32 = 2;
+
+/// `MAGIC | version | covered segment | covered offset`
+const PREFIX_LEN: usize = 20;
+
+#[derive(Debug)]
+pub(crate) struct Hint {
+    /// Every record before this position is reflected in `index`.
+    pub covered: Position,
+    pub index: BTreeMap<ByteString, Position>,
+}
+
+/// The hint file that belongs to the log at `log`.
+pub(crate) fn path_for(log: &Path) -> PathBuf {
+    let mut name = OsString::from(log.as_os_str());
+    name.push(".hint");
+    PathBuf::from(name)
+}
+
+/// Reads the hint at `path`, returning `None` if there is no usable hint.
+pub(crate) fn read(path: &Path, segments: &Segments) -> io::Result<Option<Hint>> {
+    let mut bytes = Vec::new();
+    match File::open(path) {
+        Ok(mut f) => f.read_to_end(&mut bytes)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    if bytes.len() < PREFIX_LEN + 4 || bytes[..4] != MAGIC {
+        return Ok(None);
+    }
+
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    if CKSUM.checksum(body) != LittleEndian::read_u32(checksum) {
+        return Ok(None);
+    }
+
+    let version = LittleEndian::read_u32(&body[4..8]);
+    let covered = Position {
+        segment: LittleEndian::read_u32(&body[8..12]),
+        offset: LittleEndian::read_u64(&body[12..20]),
+    };
+    if version != VERSION {
+        return Ok(None);
+    }
+    match segments.get(&covered.segment) {
+        Some(segment) if covered.offset <= segment.len()? => {}
+        _ => return Ok(None),
+    }
+
+    match bincode::deserialize(&body[PREFIX_LEN..]) {
+        Ok(index) => Ok(Some(Hint { covered, index })),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Atomically replaces the hint at `path`.
+pub(crate) fn write(
+    path: &Path,
+    covered: Position,
+    index: &BTreeMap<ByteString, Position>,
+) -> io::Result<()> {
+    let encoded =
+        bincode::serialize(index).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    let mut body = Vec::with_capacity(PREFIX_LEN + encoded.len() + 4);
+    body.write_all(&MAGIC)?;
+    body.write_u32::<LittleEndian>(VERSION)?;
+    body.write_u32::<LittleEndian>(covered.segment)?;
+    body.write_u64::<LittleEndian>(covered.offset)?;
+    body.write_all(&encoded)?;
+    let checksum = CKSUM.checksum(&body);
+    body.write_u32::<LittleEndian>(checksum)?;
+
+    let mut tmp_name = OsString::from(path.as_os_str());
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(&body)?;
+    tmp.sync_all()?;
+    drop(tmp);
+
+    fs::rename(&tmp_path, path)
+}
+
+/// Removes the hint at `path`, if there is one.
+pub(crate) fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
@@ -1,53 +1,508 @@
 use std::{
-    collections::HashMap,
-    fs::{File, OpenOptions},
-    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
-    path::Path,
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::RangeBounds,
+    path::{Path, PathBuf},
+    sync::{Arc, mpsc::Receiver},
+    time::{Duration, Instant},
 };
 
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 use crc::{CRC_32_CKSUM, Crc};
 use serde::{Deserialize, Serialize};
 
 pub mod args;
+pub mod backup;
+mod batch;
+mod cache;
+mod changes;
+mod clock;
+mod compress;
+mod error;
+pub mod export;
+mod hint;
+mod options;
+pub mod replication;
+mod resp;
+mod scan;
+mod segment;
+pub mod server;
+mod shared;
+pub mod typed;
+
+pub use batch::WriteBatch;
+pub use changes::{Change, Tail};
+pub use clock::{Clock, SystemClock};
+pub use compress::Compression;
+pub use error::Corruption;
+pub use options::{OpenOptions, SyncPolicy};
+pub use scan::Scan;
+pub use shared::{SharedKV, Snapshot};
+pub use typed::TypedKV;
+
+use cache::Cache;
+use changes::Subscribers;
+use segment::{Segment, Segments};
 
 type ByteString = Vec<u8>;
 type ByteStr = [u8];
 
+/// Version written by this build.
+///
+/// - 0: `checksum | key_len | val_len | key | value`, deletes are empty values
+/// - 1: `checksum | kind | key_len | val_len | key | value`, with the
+///   checksum covering everything after it. Records between a `BatchBegin`
+///   and its `BatchCommit` only take effect once the commit is on disk.
+/// - 2: as 1, but a record whose kind has the `EXPIRES` flag set carries an
+///   expiry time, `checksum | kind | key_len | val_len | expires_at | key | value`
+/// - 3: as 2, but the kind byte may also name the codec that compressed the
+///   stored value. The checksum covers the stored, compressed bytes.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Flag in the kind byte of a record that carries an expiry time.
+const EXPIRES: u8 = 0x80;
+
+/// Bits of the kind byte that hold the [`RecordKind`]; the rest are flags.
+const KIND_MASK: u8 = 0x0f;
+
+/// Size past which a store directory starts a new segment, unless changed
+/// with [`ActionKV::set_max_segment_size`].
+pub const DEFAULT_MAX_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Name of the hint file inside a store directory.
+const HINT_FILE: &str = "index.hint";
+
+/// Key under which the old `akv_disk` kept its index inside a version 0 log.
+/// It is not a user key, so it is left out of the index of such a log and
+/// dropped when the log is upgraded.
+const LEGACY_INDEX_KEY: &ByteStr = b"+index";
+
+/// Name of a follower's position file inside a store directory.
+const REPLICA_FILE: &str = "replica";
+
+const CKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_CKSUM);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct KeyValuePair {
     pub key: ByteString,
     pub value: ByteString,
 }
 
+/// What a log record does to its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RecordKind {
+    Put = 0,
+    Delete = 1,
+    BatchBegin = 2,
+    BatchCommit = 3,
+}
+
+impl TryFrom<u8> for RecordKind {
+    type Error = io::Error;
+
+    fn try_from(byte: u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(RecordKind::Put),
+            1 => Ok(RecordKind::Delete),
+            2 => Ok(RecordKind::BatchBegin),
+            3 => Ok(RecordKind::BatchCommit),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown record kind {:#04x}", byte),
+            )),
+        }
+    }
+}
+
+/// What [`ActionKV::load_with`] does when the log ends in a bad record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Recovery {
+    /// Return the corruption as an error.
+    #[default]
+    Strict,
+    /// Truncate the log back to the last good record.
+    TruncateTail,
+}
+
+/// Where a record lives: which segment, and how far into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Position {
+    pub segment: u32,
+    pub offset: u64,
+}
+
+/// A single decoded log record.
+#[derive(Debug)]
+pub struct Record {
+    pub kind: RecordKind,
+    pub kv: KeyValuePair,
+    /// When the record stops being visible, in milliseconds since the Unix
+    /// epoch. `None` if it never expires.
+    pub expires_at: Option<u64>,
+}
+
+impl Record {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Figures about a store, as returned by [`ActionKV::stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Live keys, leaving out any whose time to live has run out.
+    pub keys: usize,
+    pub segments: usize,
+    /// Total size of the segments on disk.
+    pub bytes: u64,
+    /// Format version of the segment being appended to.
+    pub version: u32,
+    /// Reads answered from the value cache.
+    pub cache_hits: u64,
+    /// Reads that went to disk while the value cache was enabled.
+    pub cache_misses: u64,
+    /// Bytes held by the value cache, counting keys and bookkeeping.
+    pub cache_bytes: usize,
+}
+
+/// An append-only key-value store.
+///
+/// A store is either a directory of numbered segment files, which rolls
+/// over to a new segment once the active one passes the maximum segment
+/// size, or a single log file as written by earlier versions, which is
+/// never split.
 #[derive(Debug)]
 pub struct ActionKV {
-    f: File,
-    pub index: HashMap<ByteString, u64>,
+    path: PathBuf,
+    /// `true` when `path` is a directory of segments.
+    segmented: bool,
+    segments: Segments,
+    max_segment_size: u64,
+    compression: Compression,
+    sync: SyncPolicy,
+    /// Writes appended since the active segment was last synced.
+    unsynced: u32,
+    last_sync: Instant,
+    cache: Cache,
+    subscribers: Subscribers,
+    clock: Arc<dyn Clock>,
+    /// Where a batch still open at the end of the active segment began, as
+    /// found by the last load. Cut off before the next write.
+    open_batch: Option<Position>,
+    /// `true` once a load has built `index` from the whole log.
+    loaded: bool,
+    pub index: BTreeMap<ByteString, Position>,
 }
 
 impl ActionKV {
+    /// Opens the store at `path` with the default [`OpenOptions`].
+    ///
+    /// An existing regular file is opened as a single-file store. Anything
+    /// else is treated as a store directory, and created if it is missing.
     pub fn open(path: &Path) -> io::Result<Self> {
-        let f = OpenOptions::new()
-            .read(true)
-            // .write(true)
-            .create(true)
-            .append(true)
-            .open(path)?;
-        let index = HashMap::new();
+        ActionKV::open_with(path, OpenOptions::default())
+    }
+
+    /// Like [`ActionKV::open`], with control over syncing, segment size and
+    /// compression.
+    pub fn open_with(path: &Path, options: OpenOptions) -> io::Result<Self> {
+        let segmented = !path.is_file();
+        let mut segments = if segmented {
+            fs::create_dir_all(path)?;
+            segment::open_dir(path)?
+        } else {
+            Segments::new()
+        };
+
+        if segments.is_empty() {
+            let file = if segmented {
+                segment::path_in(path, 0)
+            } else {
+                path.to_path_buf()
+            };
+            segments.insert(0, Segment::open(0, &file)?);
+        }
+
+        Ok(ActionKV::with_segments(path, segmented, segments, options))
+    }
+
+    /// Opens the existing store at `path` for reading only, never creating
+    /// or changing a file, so that it works on a store the caller cannot
+    /// write. Writes to the store fail, as does a load that needs to
+    /// recover the log.
+    pub fn open_read_only(path: &Path) -> io::Result<Self> {
+        let segmented = path.is_dir();
+        let segments = if segmented {
+            segment::open_dir_read(path)?
+        } else if path.exists() {
+            Segments::from([(0, Segment::open_read(0, path)?)])
+        } else {
+            Segments::new()
+        };
+
+        if segments.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no such store: {}", path.display()),
+            ));
+        }
+
+        Ok(ActionKV::with_segments(
+            path,
+            segmented,
+            segments,
+            OpenOptions::default(),
+        ))
+    }
+
+    fn with_segments(
+        path: &Path,
+        segmented: bool,
+        segments: Segments,
+        options: OpenOptions,
+    ) -> Self {
+        let mut store = ActionKV {
+            path: path.to_path_buf(),
+            segmented,
+            segments,
+            max_segment_size: DEFAULT_MAX_SEGMENT_SIZE,
+            compression: options.compression,
+            sync: options.sync,
+            unsynced: 0,
+            last_sync: Instant::now(),
+            cache: Cache::new(options.cache_size),
+            subscribers: Subscribers::default(),
+            clock: Arc::new(SystemClock),
+            open_batch: None,
+            loaded: false,
+            index: BTreeMap::new(),
+        };
+        store.set_max_segment_size(options.max_segment_size);
+
+        store
+    }
+
+    /// Starts a new segment once the active one reaches `bytes`. Has no
+    /// effect on a single-file store.
+    pub fn set_max_segment_size(&mut self, bytes: u64) {
+        self.max_segment_size = bytes.max(segment::HEADER_LEN + 1);
+    }
+
+    /// Compresses the values written from now on, including those rewritten
+    /// by compaction.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Changes when appended records are synced to disk from now on.
+    pub fn set_sync_policy(&mut self, sync: SyncPolicy) {
+        self.sync = sync;
+    }
 
-        Ok(ActionKV { f, index })
+    /// Keeps up to `bytes` of recently read values in memory, so that
+    /// [`ActionKV::get`] can skip the disk for hot keys. 0 turns the cache
+    /// off.
+    pub fn set_cache_size(&mut self, bytes: usize) {
+        self.cache.resize(bytes);
+    }
+
+    /// Replaces the clock that decides when keys expire.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Format version of the segment being appended to.
+    pub fn version(&self) -> u32 {
+        self.active().version
+    }
+
+    pub fn stats(&self) -> io::Result<Stats> {
+        let mut bytes = 0;
+        for segment in self.segments.values() {
+            bytes += segment.len()?;
+        }
+
+        Ok(Stats {
+            keys: self.keys().count(),
+            segments: self.segments.len(),
+            bytes,
+            version: self.version(),
+            cache_hits: self.cache.hits(),
+            cache_misses: self.cache.misses(),
+            cache_bytes: self.cache.used(),
+        })
+    }
+
+    /// Ids of the segments that make up the log, oldest first.
+    pub fn segment_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.segments.keys().copied()
+    }
+
+    fn active(&self) -> &Segment {
+        match self.segments.last_key_value() {
+            Some((_, segment)) => segment,
+            None => unreachable!("a store always has an active segment"),
+        }
+    }
+
+    fn active_mut(&mut self) -> &mut Segment {
+        match self.segments.last_entry() {
+            Some(entry) => entry.into_mut(),
+            None => unreachable!("a store always has an active segment"),
+        }
+    }
+
+    fn segment(&self, id: u32) -> io::Result<&Segment> {
+        segment_of(&self.segments, id)
     }
 
     pub fn load(&mut self) -> io::Result<()> {
-        let mut f = BufReader::new(&mut self.f);
+        self.load_with(Recovery::Strict)
+    }
+
+    /// Like [`ActionKV::load`], but lets the caller decide what happens when
+    /// the log ends in a bad record.
+    ///
+    /// With [`Recovery::TruncateTail`] a record in the active segment that
+    /// runs past the end of the file, with no valid record anywhere after its
+    /// start, is treated as a write torn by a crash, and the segment is
+    /// truncated back to the last good record,
+    /// along with a batch left without its commit marker. Corruption
+    /// anywhere else is still returned as an error.
+    ///
+    /// Otherwise the log is never changed: an unfinished batch is skipped,
+    /// since its writer may still be appending to it, and only cut off
+    /// before this store's own next write.
+    pub fn load_with(&mut self, recovery: Recovery) -> io::Result<()> {
+        let err = match (self.replay(), recovery) {
+            (Ok(()), Recovery::Strict) => return Ok(()),
+            (Ok(()), Recovery::TruncateTail) => return self.drop_open_batch(),
+            (Err(err), _) => err,
+        };
+
+        let active = self.active();
+        let offset = match (recovery, Corruption::from_io(&err)) {
+            (Recovery::TruncateTail, Some(corruption))
+                if corruption.segment == active.id && corruption.is_torn() =>
+            {
+                corruption.offset
+            }
+            _ => return Err(err),
+        };
+
+        // a damaged length can make a record seem to run past the end of
+        // the file, so the lengths it claims cannot be trusted to find the
+        // records after it
+        if ActionKV::has_record_from(active, offset + 1)? {
+            return Err(err);
+        }
+
+        active.file.set_len(offset)?;
+        active.file.sync_all()?;
+
+        // the cut may have left a batch without its commit marker
+        self.index.clear();
+        self.replay()?;
+        self.drop_open_batch()
+    }
+
+    fn replay(&mut self) -> io::Result<()> {
+        self.cache.clear();
+        self.open_batch = None;
+        self.loaded = false;
+        let first = match self.segments.first_key_value() {
+            Some((_, segment)) => segment,
+            None => return Ok(()),
+        };
+        let mut start = Position {
+            segment: first.id,
+            offset: first.data_start(),
+        };
+
+        if let Some(hint) = hint::read(&self.hint_path(), &self.segments)? {
+            self.index = hint.index;
+            start = hint.covered;
+        }
+
+        let now = clock::millis(&*self.clock);
+        let index = &mut self.index;
+        let mut open_batch = None;
+        for segment in self.segments.range(start.segment..).map(|(_, s)| s) {
+            let from = if segment.id == start.segment {
+                start.offset
+            } else {
+                segment.data_start()
+            };
+
+            let legacy = segment.version == 0;
+            let unfinished = ActionKV::replay_records(segment, from, |position, record| {
+                if legacy && record.kv.key == LEGACY_INDEX_KEY {
+                    return;
+                }
+                match record.kind {
+                    // an expired put still hides the values written before it
+                    RecordKind::Put if record.is_expired(now) => index.remove(&record.kv.key),
+                    RecordKind::Put => index.insert(record.kv.key, position),
+                    RecordKind::Delete => index.remove(&record.kv.key),
+                    _ => None,
+                };
+            })?;
+
+            // only a batch at the end of the log can still be being written
+            open_batch = unfinished.map(|offset| Position {
+                segment: segment.id,
+                offset,
+            });
+        }
+        self.open_batch = open_batch;
+        self.loaded = true;
+
+        Ok(())
+    }
+
+    /// Truncates the active segment back to the batch that the last load
+    /// found open, so that it cannot swallow the records appended after it.
+    fn drop_open_batch(&mut self) -> io::Result<()> {
+        let Some(begin) = self.open_batch.take() else {
+            return Ok(());
+        };
+
+        let active = self.active();
+        if begin.segment == active.id {
+            active.file.set_len(begin.offset)?;
+            active.file.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    /// Feeds every record of `segment` from `start` onwards to `apply`,
+    /// holding back the records of a batch until its commit marker has been
+    /// read.
+    ///
+    /// Returns the offset of the batch that was still open at the end of the
+    /// segment, if any.
+    fn replay_records<F>(segment: &Segment, start: u64, mut apply: F) -> io::Result<Option<u64>>
+    where
+        F: FnMut(Position, Record),
+    {
+        let mut f = segment.reader_at(start)?;
+
+        let mut batch: Option<(u64, Vec<(Position, Record)>)> = None;
 
         loop {
             // let position = f.seek(SeekFrom::Current(0))?;
-            let position = f.stream_position()?;
-            let maybe_kv = ActionKV::process_record(&mut f);
-            let kv = match maybe_kv {
-                Ok(kv) => kv,
+            let offset = f.stream_position()?;
+            let position = Position {
+                segment: segment.id,
+                offset,
+            };
+            let maybe_record = ActionKV::process_record(&mut f, segment.version, position);
+            let record = match maybe_record {
+                Ok(record) => record,
                 Err(err) => match err.kind() {
                     io::ErrorKind::UnexpectedEof => {
                         break;
@@ -56,40 +511,259 @@ impl ActionKV {
                 },
             };
 
-            self.index.insert(kv.key, position);
+            match (record.kind, &mut batch) {
+                // a begin inside an open batch means the open one was abandoned
+                (RecordKind::BatchBegin, _) => batch = Some((offset, Vec::new())),
+                (RecordKind::BatchCommit, Some(_)) => {
+                    if let Some((_, records)) = batch.take() {
+                        for (position, record) in records {
+                            apply(position, record);
+                        }
+                    }
+                }
+                (RecordKind::BatchCommit, None) => {}
+                (_, Some((_, records))) => records.push((position, record)),
+                (_, None) => apply(position, record),
+            }
         }
 
-        Ok(())
+        Ok(batch.map(|(begin, _)| begin))
+    }
+
+    /// Checks every record in the log and returns the ones that are bad.
+    ///
+    /// Unlike [`ActionKV::load`] this does not stop at the first bad record:
+    /// it trusts the lengths in the bad record's header to skip over it, so a
+    /// single flipped bit is reported without hiding the records after it.
+    pub fn fsck(&mut self) -> io::Result<Vec<Corruption>> {
+        let mut bad = Vec::new();
+        for segment in self.segments.values() {
+            let (mut found, _) = ActionKV::check_from(segment, segment.data_start())?;
+            bad.append(&mut found);
+        }
+
+        Ok(bad)
     }
 
-    fn process_record<R: Read>(f: &mut R) -> io::Result<KeyValuePair> {
-        let saved_checksum = f.read_u32::<LittleEndian>()?;
-        let key_len = f.read_u32::<LittleEndian>()?;
-        let val_len = f.read_u32::<LittleEndian>()?;
-        let data_len = key_len + val_len;
+    /// Scans `segment` from `start` to its end, returning the bad records and
+    /// the end offset of the last good one (or `start` if there is none).
+    fn check_from(segment: &Segment, start: u64) -> io::Result<(Vec<Corruption>, u64)> {
+        let version = segment.version;
+        let file_len = segment.len()?;
+        let mut f = segment.reader_at(start)?;
+
+        let mut bad = Vec::new();
+        let mut end = start;
+        let mut offset = start;
 
-        let mut data = ByteString::with_capacity(data_len as usize);
+        loop {
+            let position = Position {
+                segment: segment.id,
+                offset,
+            };
+            match ActionKV::process_record(&mut f, version, position) {
+                Ok(_) => {
+                    offset = f.stream_position()?;
+                    end = offset;
+                }
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => {
+                    let corruption = match Corruption::from_io(&err) {
+                        Some(corruption) => corruption.clone(),
+                        None => return Err(err),
+                    };
+
+                    let kind = segment.read_kind_at(corruption.offset)?;
+                    offset = corruption.offset
+                        + ActionKV::record_len(
+                            version,
+                            kind,
+                            corruption.key_len,
+                            corruption.val_len,
+                        );
+                    let torn = corruption.is_torn();
+                    bad.push(corruption);
+
+                    if torn || offset >= file_len {
+                        break;
+                    }
+                    f.seek(SeekFrom::Start(offset))?;
+                }
+            }
+        }
+
+        Ok((bad, end))
+    }
+
+    /// `true` if a record with a good checksum starts at any byte of
+    /// `segment` from `start` on.
+    fn has_record_from(segment: &Segment, start: u64) -> io::Result<bool> {
+        let version = segment.version;
+        let header_len = if version == 0 { 12 } else { 13 };
+        let mut rest = Vec::new();
+        segment.reader_at(start)?.read_to_end(&mut rest)?;
+
+        for at in 0..rest.len() {
+            let candidate = &rest[at..];
+            if candidate.len() < header_len {
+                break;
+            }
+
+            // skip anything that claims to run past the end of the file
+            let lens_at = header_len - 8;
+            let key_len = LittleEndian::read_u32(&candidate[lens_at..]);
+            let val_len = LittleEndian::read_u32(&candidate[lens_at + 4..]);
+            let kind = if version == 0 { 0 } else { candidate[4] };
+            if ActionKV::record_len(version, kind, key_len, val_len) > candidate.len() as u64 {
+                continue;
+            }
+
+            let position = Position {
+                segment: segment.id,
+                offset: start + at as u64,
+            };
+            if ActionKV::process_record(&mut &candidate[..], version, position).is_ok() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Length of a record from the fields of its header. `kind` is ignored
+    /// for version 0, which has no kind byte.
+    fn record_len(version: u32, kind: u8, key_len: u32, val_len: u32) -> u64 {
+        let header_len = if version == 0 { 12 } else { 13 };
+        header_len + ActionKV::expiry_len(version, kind) + key_len as u64 + val_len as u64
+    }
+
+    /// Length of the expiry time that follows the header of a record.
+    fn expiry_len(version: u32, kind: u8) -> u64 {
+        if version != 0 && kind & EXPIRES != 0 {
+            8
+        } else {
+            0
+        }
+    }
+
+    fn process_record<R: Read>(f: &mut R, version: u32, position: Position) -> io::Result<Record> {
+        let mut header = [0; 13];
+        let header = if version == 0 {
+            &mut header[..12]
+        } else {
+            &mut header[..]
+        };
+
+        let filled = ActionKV::read_full(f, header)?;
+        if filled == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        // the lengths are always the last eight bytes of the header
+        let lens_at = header.len() - 8;
+        let field = |at: usize| {
+            if filled >= at + 4 {
+                LittleEndian::read_u32(&header[at..at + 4])
+            } else {
+                0
+            }
+        };
+        let saved_checksum = field(0);
+        let key_len = field(lens_at);
+        let val_len = field(lens_at + 4);
+        let mut corruption = Corruption {
+            segment: position.segment,
+            offset: position.offset,
+            key_len,
+            val_len,
+            saved_checksum,
+            checksum: None,
+        };
+
+        if filled < header.len() {
+            return Err(corruption.into());
+        }
+
+        // an expiry time is read as if it were part of the data
+        let expiry_len = ActionKV::expiry_len(version, header[4]);
+        let data_len = expiry_len + key_len as u64 + val_len as u64;
+        let mut data = ByteString::new();
         {
-            f.by_ref().take(data_len as u64).read_to_end(&mut data)?;
+            f.by_ref().take(data_len).read_to_end(&mut data)?;
+        }
+        if data.len() as u64 != data_len {
+            return Err(corruption.into());
         }
-        debug_assert_eq!(data.len(), data_len as usize);
-        // let checksum = crc32::checksum_ieee(&data);
-        let checksum = Crc::<u32>::new(&CRC_32_CKSUM).checksum(&data);
+
+        let checksum = if version == 0 {
+            // let checksum = crc32::checksum_ieee(&data);
+            CKSUM.checksum(&data)
+        } else {
+            let mut digest = CKSUM.digest();
+            digest.update(&header[4..]);
+            digest.update(&data);
+            digest.finalize()
+        };
         if checksum != saved_checksum {
-            panic!(
-                "data corruption encountered ({:08x} != {:08x})",
-                checksum, saved_checksum
-            )
+            corruption.checksum = Some(checksum);
+            return Err(corruption.into());
         }
 
-        let value = data.split_off(key_len as usize);
+        let expires_at = match expiry_len {
+            0 => None,
+            _ => Some(LittleEndian::read_u64(&data[..8])),
+        };
+        let value = data.split_off(expiry_len as usize + key_len as usize);
+        data.drain(..expiry_len as usize);
         let key = data;
 
-        Ok(KeyValuePair { key, value })
+        let kind = match version {
+            // version 0 could only delete by writing an empty value
+            0 if value.is_empty() => RecordKind::Delete,
+            0 => RecordKind::Put,
+            _ if header[4] & !(KIND_MASK | EXPIRES | compress::FLAGS) != 0 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown record flags {:#04x}", header[4] & !KIND_MASK),
+                ));
+            }
+            _ => RecordKind::try_from(header[4] & KIND_MASK)?,
+        };
+        let value = match version {
+            0 => value,
+            _ => compress::decompress(header[4], value)?,
+        };
+
+        Ok(Record {
+            kind,
+            kv: KeyValuePair { key, value },
+            expires_at,
+        })
     }
 
-    pub fn seek_to_end(&mut self) -> io::Result<u64> {
-        self.f.seek(SeekFrom::End(0))
+    /// Reads until `buf` is full or the reader is exhausted, returning how
+    /// many bytes were read.
+    fn read_full<R: Read>(f: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match f.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(filled)
+    }
+
+    /// Position just past the last record of the active segment.
+    pub fn seek_to_end(&mut self) -> io::Result<Position> {
+        let active = self.active();
+        Ok(Position {
+            segment: active.id,
+            offset: active.len()?,
+        })
     }
 
     pub fn get(&mut self, key: &ByteStr) -> io::Result<Option<ByteString>> {
@@ -98,45 +772,91 @@ impl ActionKV {
             Some(position) => *position,
         };
 
-        let kv = self.get_at(position)?;
+        let now = clock::millis(&*self.clock);
+        let expired = |expires_at: Option<u64>| expires_at.is_some_and(|at| at <= now);
 
-        Ok(Some(kv.value))
-    }
+        if let Some(cached) = self.cache.get(key, position) {
+            return Ok(match expired(cached.expires_at) {
+                true => None,
+                false => Some(cached.value),
+            });
+        }
 
-    pub fn get_at(&mut self, position: u64) -> io::Result<KeyValuePair> {
-        let mut f = BufReader::new(&mut self.f);
-        f.seek(SeekFrom::Start(position))?;
-        let kv = ActionKV::process_record(&mut f)?;
+        let record = self.segment(position.segment)?.read_at(position.offset)?;
+        if expired(record.expires_at) {
+            return Ok(None);
+        }
+        if self.cache.is_enabled() {
+            self.cache
+                .insert(key, position, &record.kv.value, record.expires_at);
+        }
 
-        Ok(kv)
+        Ok(Some(record.kv.value))
     }
 
-    pub fn find(&mut self, target: &ByteStr) -> io::Result<Option<(u64, ByteString)>> {
-        let mut f = BufReader::new(&mut self.f);
+    pub fn get_at(&mut self, position: Position) -> io::Result<KeyValuePair> {
+        read_kv(&self.segments, position)
+    }
 
-        let mut found: Option<(u64, ByteString)> = None;
+    /// All live keys, in order.
+    ///
+    /// Each key's expiry time is read from its record's header, but the
+    /// record is not checked: one that cannot be read is listed, and left
+    /// for [`ActionKV::get`] to report.
+    pub fn keys(&self) -> impl Iterator<Item = &ByteStr> {
+        let now = clock::millis(&*self.clock);
+        live_keys(&self.segments, self.index.iter(), now)
+    }
 
-        loop {
-            // let position = f.seek(SeekFrom::Current(0))?;
-            let position = f.stream_position()?;
+    /// Live records whose keys fall within `range`, in key order.
+    ///
+    /// ```no_run
+    /// # use libactionkv::ActionKV;
+    /// # use std::ops::Bound;
+    /// # let mut store = ActionKV::open("store".as_ref())?;
+    /// # store.load()?;
+    /// let start: &[u8] = b"user:100";
+    /// let end: &[u8] = b"user:200";
+    /// for kv in store.scan((Bound::Included(start), Bound::Excluded(end))) {
+    ///     println!("{:?}", kv?.value);
+    /// }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn scan<R>(&self, range: R) -> Scan<'_>
+    where
+        R: RangeBounds<ByteStr>,
+    {
+        let now = clock::millis(&*self.clock);
+        Scan::new(&self.segments, self.index.range(range), now)
+    }
 
-            let maybe_kv = ActionKV::process_record(&mut f);
-            let kv = match maybe_kv {
-                Ok(kv) => kv,
-                Err(err) => match err.kind() {
-                    io::ErrorKind::UnexpectedEof => {
-                        break;
-                    }
-                    _ => return Err(err),
-                },
-            };
+    /// Live records whose keys start with `prefix`, in key order.
+    pub fn scan_prefix(&self, prefix: &ByteStr) -> Scan<'_> {
+        let now = clock::millis(&*self.clock);
+        Scan::new(
+            &self.segments,
+            self.index.range(scan::prefix_range(prefix)),
+            now,
+        )
+    }
 
-            if kv.key == target {
-                found = Some((position, kv.value));
-            }
+    pub fn find(&mut self, target: &ByteStr) -> io::Result<Option<(Position, ByteString)>> {
+        let mut found: Option<(Position, ByteString)> = None;
+        let now = clock::millis(&*self.clock);
 
-            // important to keep looping until the end of the file,
-            // in case the key has been overwritten
+        // important to look at every record up to the end of the log,
+        // in case the key has been overwritten
+        for segment in self.segments.values() {
+            ActionKV::replay_records(segment, segment.data_start(), |position, record| {
+                if record.kv.key == target {
+                    found = match record.kind {
+                        RecordKind::Put if !record.is_expired(now) => {
+                            Some((position, record.kv.value))
+                        }
+                        _ => None,
+                    };
+                }
+            })?;
         }
 
         Ok(found)
@@ -145,37 +865,538 @@ impl ActionKV {
     pub fn insert(&mut self, key: &ByteStr, value: &ByteStr) -> io::Result<()> {
         let position = self.insert_but_ignore_index(key, value)?;
 
-        self.index.insert(key.to_vec(), position);
+        let old = self.index.insert(key.to_vec(), position);
+        self.subscribers.notify(RecordKind::Put, key, old, position);
         Ok(())
     }
 
-    pub fn insert_but_ignore_index(&mut self, key: &ByteStr, value: &ByteStr) -> io::Result<u64> {
-        let mut f = BufWriter::new(&mut self.f);
+    pub fn insert_but_ignore_index(
+        &mut self,
+        key: &ByteStr,
+        value: &ByteStr,
+    ) -> io::Result<Position> {
+        self.append(RecordKind::Put, key, value, None)
+    }
 
-        let key_len = key.len();
-        let val_len = value.len();
-        let mut tmp = ByteString::with_capacity(key_len + val_len);
-        for byte in key {
-            tmp.push(*byte);
+    /// Like [`ActionKV::insert`], but `key` reads as missing once `ttl` has
+    /// passed, and is dropped by the next load or compaction after that.
+    pub fn insert_with_ttl(
+        &mut self,
+        key: &ByteStr,
+        value: &ByteStr,
+        ttl: Duration,
+    ) -> io::Result<()> {
+        let expires_at = self.expiry(ttl);
+        let position = self.append(RecordKind::Put, key, value, Some(expires_at))?;
+
+        let old = self.index.insert(key.to_vec(), position);
+        self.subscribers.notify(RecordKind::Put, key, old, position);
+        Ok(())
+    }
+
+    /// The expiry time, in milliseconds since the Unix epoch, of a record
+    /// written now with `ttl`.
+    fn expiry(&self, ttl: Duration) -> u64 {
+        let ttl = ttl.as_millis().try_into().unwrap_or(u64::MAX);
+        clock::millis(&*self.clock).saturating_add(ttl)
+    }
+
+    fn append(
+        &mut self,
+        kind: RecordKind,
+        key: &ByteStr,
+        value: &ByteStr,
+        expires_at: Option<u64>,
+    ) -> io::Result<Position> {
+        self.prepare_append()?;
+        self.cache.remove(key);
+
+        let compression = self.compression;
+        let active = self.active();
+        let mut f = BufWriter::new(&*active.file);
+
+        let offset = f.seek(SeekFrom::End(0))?;
+        ActionKV::write_record(&mut f, kind, key, value, expires_at, compression)?;
+        f.flush()?;
+        let position = Position {
+            segment: active.id,
+            offset,
+        };
+        drop(f);
+
+        self.wrote()?;
+        Ok(position)
+    }
+
+    /// Gets the active segment ready for the next write: upgrades an older
+    /// format, and rolls over to a new segment if the active one is full.
+    fn prepare_append(&mut self) -> io::Result<()> {
+        self.drop_open_batch()?;
+        match self.version() {
+            0 => self.upgrade()?,
+            version if version < FORMAT_VERSION => self.active_mut().bump_version()?,
+            _ => {}
         }
 
-        for byte in value {
-            tmp.push(*byte);
+        if self.segmented && self.active().len()? >= self.max_segment_size {
+            self.roll()?;
         }
 
+        Ok(())
+    }
+
+    /// Seals the active segment and starts the next one.
+    fn roll(&mut self) -> io::Result<()> {
+        self.sync()?;
+
+        let id = self.active().id + 1;
+        let segment = Segment::open(id, &segment::path_in(&self.path, id))?;
+        sync_dir(&self.path)?;
+        self.segments.insert(id, segment);
+
+        Ok(())
+    }
+
+    /// Writes one `checksum | kind | key_len | val_len | [expires_at] | key |
+    /// value` record to `f` and returns the number of bytes written.
+    fn write_record<W: Write>(
+        f: &mut W,
+        kind: RecordKind,
+        key: &ByteStr,
+        value: &ByteStr,
+        expires_at: Option<u64>,
+        compression: Compression,
+    ) -> io::Result<u64> {
+        let (mut flags, value) = compress::compress(compression, value)?;
+        if expires_at.is_some() {
+            flags |= EXPIRES;
+        }
+
+        let key_len = key.len();
+        let val_len = value.len();
+        let mut tmp = ByteString::with_capacity(17 + key_len + val_len);
+        tmp.push(kind as u8 | flags);
+        tmp.write_u32::<LittleEndian>(key_len as u32)?;
+        tmp.write_u32::<LittleEndian>(val_len as u32)?;
+        if let Some(expires_at) = expires_at {
+            tmp.write_u64::<LittleEndian>(expires_at)?;
+        }
+        tmp.extend_from_slice(key);
+        tmp.extend_from_slice(&value);
+
         // let checksum = crc32::checksum_ieee(&tmp);
-        let checksum = Crc::<u32>::new(&CRC_32_CKSUM).checksum(&tmp);
+        let checksum = CKSUM.checksum(&tmp);
 
-        let next_byte = SeekFrom::End(0);
-        // let current_position = f.seek(SeekFrom::Current(0))?;
-        let current_position = f.stream_position()?;
-        f.seek(next_byte)?;
         f.write_u32::<LittleEndian>(checksum)?;
-        f.write_u32::<LittleEndian>(key_len as u32)?;
-        f.write_u32::<LittleEndian>(val_len as u32)?;
         f.write_all(&tmp)?;
 
-        Ok(current_position)
+        Ok(4 + tmp.len() as u64)
+    }
+
+    /// Inserts every pair from `pairs`, in order, and returns how many were
+    /// inserted.
+    ///
+    /// Unlike calling [`ActionKV::insert`] in a loop, the records share one
+    /// buffered writer per segment. Pairs are not applied atomically: if
+    /// `pairs` yields an error, the ones before it are kept and the error is
+    /// returned.
+    pub fn insert_all<I>(&mut self, pairs: I) -> io::Result<usize>
+    where
+        I: IntoIterator<Item = io::Result<KeyValuePair>>,
+    {
+        let mut pairs = pairs.into_iter().peekable();
+        let mut inserted = 0;
+
+        while pairs.peek().is_some() {
+            self.prepare_append()?;
+            let compression = self.compression;
+            let limit = match self.segmented {
+                true => self.max_segment_size,
+                false => u64::MAX,
+            };
+
+            let active = self.active();
+            let id = active.id;
+            let mut f = BufWriter::new(&*active.file);
+            let mut offset = f.seek(SeekFrom::End(0))?;
+
+            // fill the active segment, then go round again to roll over
+            let mut written = Vec::new();
+            let mut result = Ok(());
+            while offset < limit {
+                let kv = match pairs.next() {
+                    None => break,
+                    Some(Ok(kv)) => kv,
+                    Some(Err(err)) => {
+                        result = Err(err);
+                        break;
+                    }
+                };
+
+                let put = RecordKind::Put;
+                match ActionKV::write_record(&mut f, put, &kv.key, &kv.value, None, compression) {
+                    Ok(len) => {
+                        written.push((kv.key, offset));
+                        offset += len;
+                    }
+                    Err(err) => {
+                        result = Err(err);
+                        break;
+                    }
+                }
+            }
+            f.flush()?;
+            drop(f);
+
+            if !written.is_empty() {
+                self.wrote()?;
+            }
+            inserted += written.len();
+            for (key, offset) in written {
+                let position = Position {
+                    segment: id,
+                    offset,
+                };
+                self.cache.remove(&key);
+                let old = self.index.insert(key.clone(), position);
+                self.subscribers
+                    .notify(RecordKind::Put, &key, old, position);
+            }
+            result?;
+        }
+
+        Ok(inserted)
+    }
+
+    /// Applies every write in `batch`, or none of them if the process dies
+    /// before the batch's commit marker reaches the log.
+    pub fn write(&mut self, batch: WriteBatch) -> io::Result<()> {
+        let positions = self.append_batch(&batch)?;
+        batch.apply(positions, &mut self.index, &mut self.subscribers);
+
+        Ok(())
+    }
+
+    /// Writes `batch` to the log and returns the position of each of its
+    /// records, leaving `index` alone. A batch never spans two segments.
+    fn append_batch(&mut self, batch: &WriteBatch) -> io::Result<Vec<Position>> {
+        if batch.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.prepare_append()?;
+        for (_, key, _) in &batch.ops {
+            self.cache.remove(key);
+        }
+
+        let compression = self.compression;
+        let active = self.active();
+        let mut positions = Vec::with_capacity(batch.len());
+        let mut f = BufWriter::new(&*active.file);
+        let mut offset = f.seek(SeekFrom::End(0))?;
+
+        offset += ActionKV::write_record(
+            &mut f,
+            RecordKind::BatchBegin,
+            b"",
+            b"",
+            None,
+            Compression::None,
+        )?;
+        for (kind, key, value) in &batch.ops {
+            positions.push(Position {
+                segment: active.id,
+                offset,
+            });
+            offset += ActionKV::write_record(&mut f, *kind, key, value, None, compression)?;
+        }
+        ActionKV::write_record(
+            &mut f,
+            RecordKind::BatchCommit,
+            b"",
+            b"",
+            None,
+            Compression::None,
+        )?;
+
+        f.flush()?;
+        drop(f);
+
+        self.wrote()?;
+        Ok(positions)
+    }
+
+    /// Rewrites a version 0 log in the current format before the first write,
+    /// so that a file never mixes record layouts.
+    fn upgrade(&mut self) -> io::Result<()> {
+        self.index.clear();
+        self.load()?;
+        self.compact()
+    }
+
+    /// Rewrites every segment, oldest first, so that each holds only the live
+    /// records it already contained.
+    ///
+    /// Superseded records, expired keys and tombstones are dropped, sealed
+    /// segments left empty are removed, and an older log is upgraded to
+    /// [`FORMAT_VERSION`]. Each new segment is written next to the old one,
+    /// synced, and then renamed over it, so a crash part-way through leaves
+    /// every segment either old or new but intact. `index` is updated to
+    /// point into the compacted segments and saved as a fresh hint.
+    ///
+    /// Fails unless [`ActionKV::load`] has been called, since compaction
+    /// keeps only the records `index` points at.
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.check_loaded()?;
+
+        // the old hint points into the old segments
+        hint::remove(&self.hint_path())?;
+
+        // once every older segment holds only live records, no tombstone
+        // has anything left to shadow
+        let ids: Vec<u32> = self.segments.keys().copied().collect();
+        for id in ids {
+            self.rewrite_segment(id, false)?;
+        }
+
+        self.save_hint()
+    }
+
+    /// Like [`ActionKV::compact`], but only rewrites segment `id`.
+    ///
+    /// Tombstones are kept for keys that are no longer live unless `id` is
+    /// the oldest segment, since an older segment may still hold a value
+    /// they hide.
+    pub fn compact_segment(&mut self, id: u32) -> io::Result<()> {
+        self.check_loaded()?;
+        self.segment(id)?;
+        let keep_tombstones = self.segments.keys().next() != Some(&id);
+
+        hint::remove(&self.hint_path())?;
+        self.rewrite_segment(id, keep_tombstones)?;
+
+        self.save_hint()
+    }
+
+    /// `index` only describes the whole log once it has been loaded, and
+    /// compaction and hints trust it to.
+    fn check_loaded(&self) -> io::Result<()> {
+        match self.loaded {
+            true => Ok(()),
+            false => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the store must be loaded first",
+            )),
+        }
+    }
+
+    fn rewrite_segment(&mut self, id: u32, keep_tombstones: bool) -> io::Result<()> {
+        let old = self.segment(id)?.clone();
+        // records are about to move, and the cache is keyed by position
+        self.cache.clear();
+
+        let mut live: Vec<(u64, &ByteString)> = self
+            .index
+            .iter()
+            .filter(|(_, position)| position.segment == id)
+            .map(|(key, position)| (position.offset, key))
+            .collect();
+        live.sort_unstable();
+
+        let mut tombstones = BTreeSet::new();
+        if keep_tombstones {
+            let index = &self.index;
+            ActionKV::replay_records(&old, old.data_start(), |_, record| {
+                if record.kind == RecordKind::Delete && !index.contains_key(&record.kv.key) {
+                    tombstones.insert(record.kv.key);
+                }
+            })?;
+        }
+
+        let tmp_path = compaction_path(&old.path);
+        let mut tmp = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)?;
+
+        let now = clock::millis(&*self.clock);
+        let compression = self.compression;
+        let mut moved = Vec::with_capacity(live.len());
+        let mut expired = Vec::new();
+        {
+            let mut w = BufWriter::new(&mut tmp);
+            Segment::write_header(&mut w)?;
+            let mut next_offset = segment::HEADER_LEN;
+
+            for (offset, _) in live {
+                let record = old.read_at(offset)?;
+                let expired_now = record.is_expired(now);
+                let kv = record.kv;
+                if expired_now {
+                    if keep_tombstones {
+                        tombstones.insert(kv.key.clone());
+                    }
+                    expired.push(kv.key);
+                    continue;
+                }
+
+                let written = ActionKV::write_record(
+                    &mut w,
+                    RecordKind::Put,
+                    &kv.key,
+                    &kv.value,
+                    record.expires_at,
+                    compression,
+                )?;
+                moved.push((kv.key, next_offset));
+                next_offset += written;
+            }
+
+            for key in &tombstones {
+                ActionKV::write_record(
+                    &mut w,
+                    RecordKind::Delete,
+                    key,
+                    b"",
+                    None,
+                    Compression::None,
+                )?;
+            }
+
+            w.flush()?;
+        }
+        tmp.sync_all()?;
+        drop(tmp);
+
+        for key in &expired {
+            self.index.remove(key);
+        }
+
+        let is_active = id == self.active().id;
+        if moved.is_empty() && tombstones.is_empty() && !is_active {
+            fs::remove_file(&tmp_path)?;
+            fs::remove_file(&old.path)?;
+            sync_dir(&self.path)?;
+            self.segments.remove(&id);
+            return Ok(());
+        }
+
+        fs::rename(&tmp_path, &old.path)?;
+        sync_parent_dir(&old.path)?;
+        self.segments.insert(id, Segment::open(id, &old.path)?);
+        if self.open_batch.is_some_and(|begin| begin.segment == id) {
+            self.open_batch = None;
+        }
+
+        for (key, offset) in moved {
+            self.index.insert(
+                key,
+                Position {
+                    segment: id,
+                    offset,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Writes `index` to the hint file, so that the next [`ActionKV::load`]
+    /// only has to replay records appended after this call.
+    ///
+    /// `index` must describe the whole log, which holds as long as the store
+    /// was loaded and then only changed through `insert`, `update` and `delete`.
+    /// Fails if the store was never loaded.
+    pub fn save_hint(&mut self) -> io::Result<()> {
+        self.check_loaded()?;
+        // the hint must never cover records that could still be lost
+        let covered = self.synced_end()?;
+
+        hint::write(&self.hint_path(), covered, &self.index)
+    }
+
+    /// Copies the log as it stands into the new snapshot directory `dest`,
+    /// together with the index and a [`backup::Manifest`], which is also
+    /// returned. The snapshot is itself a store directory; see
+    /// [`backup::restore`] to copy it back into place after checking it.
+    ///
+    /// As with [`ActionKV::save_hint`], `index` must describe the whole log.
+    /// A single-file store is snapshotted as a store directory.
+    pub fn snapshot(&mut self, dest: &Path) -> io::Result<backup::Manifest> {
+        let covered = self.synced_end()?;
+
+        backup::write(dest, &self.segments, covered, &self.index)
+    }
+
+    /// Counts one write against the [`SyncPolicy`], syncing if it is due.
+    fn wrote(&mut self) -> io::Result<()> {
+        self.unsynced = self.unsynced.saturating_add(1);
+
+        let due = match self.sync {
+            SyncPolicy::Always => true,
+            SyncPolicy::EveryN(n) => self.unsynced >= n,
+            SyncPolicy::Interval(interval) => self.last_sync.elapsed() >= interval,
+            SyncPolicy::Never => false,
+        };
+        if due {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Hands every record written so far to the operating system without
+    /// waiting for it to reach the disk. Once this returns the records
+    /// survive the process crashing, but not a power failure; that takes
+    /// [`ActionKV::sync`]. Unlike `sync`, this leaves the [`SyncPolicy`]'s
+    /// count of unsynced writes alone.
+    pub fn flush(&mut self) -> io::Result<()> {
+        (&*self.active().file).flush()
+    }
+
+    /// Flushes and then puts every record written so far on disk, whatever
+    /// the [`SyncPolicy`].
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.active().file.sync_data()?;
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+
+        Ok(())
+    }
+
+    /// Syncs the active segment and returns the end of its last committed
+    /// record.
+    fn synced_end(&mut self) -> io::Result<Position> {
+        self.sync()?;
+
+        if let Some(begin) = self.open_batch {
+            return Ok(begin);
+        }
+        let active = self.active();
+        Ok(Position {
+            segment: active.id,
+            offset: active.len()?,
+        })
+    }
+
+    fn hint_path(&self) -> PathBuf {
+        if self.segmented {
+            self.path.join(HINT_FILE)
+        } else {
+            hint::path_for(&self.path)
+        }
+    }
+
+    /// Where a [`replication::Follower`] of this store keeps its position.
+    fn replica_path(&self) -> PathBuf {
+        if self.segmented {
+            self.path.join(REPLICA_FILE)
+        } else {
+            let mut name = OsString::from(self.path.as_os_str());
+            name.push(".replica");
+            PathBuf::from(name)
+        }
     }
 
     #[inline]
@@ -183,8 +1404,102 @@ impl ActionKV {
         self.insert(key, value)
     }
 
-    #[inline]
     pub fn delete(&mut self, key: &ByteStr) -> io::Result<()> {
-        self.insert(key, b"")
+        let position = self.append(RecordKind::Delete, key, b"", None)?;
+
+        let old = self.index.remove(key);
+        self.subscribers
+            .notify(RecordKind::Delete, key, old, position);
+        Ok(())
+    }
+
+    /// Sends a [`Change`] for every key starting with `prefix` that is
+    /// written or deleted through this store from now on, until the
+    /// receiver is dropped.
+    ///
+    /// The channel is unbounded, so a subscriber that stops reading holds on
+    /// to every change made after that. Compaction moves records without
+    /// changing them and sends nothing, and neither does
+    /// [`ActionKV::insert_but_ignore_index`].
+    pub fn subscribe(&mut self, prefix: &ByteStr) -> Receiver<Change> {
+        self.subscribers.subscribe(prefix)
+    }
+
+    /// Follows the log on disk from `from`, which is usually a position
+    /// saved from an earlier [`Tail::position`] or the result of
+    /// [`ActionKV::seek_to_end`].
+    pub fn tail(&self, from: Position) -> io::Result<Tail> {
+        Tail::open(&self.path, self.segmented, from)
     }
 }
+
+fn segment_of(segments: &Segments, id: u32) -> io::Result<&Segment> {
+    segments.get(&id).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("segment {} does not exist", id),
+        )
+    })
+}
+
+/// Reads the key-value pair at `position`.
+fn read_kv(segments: &Segments, position: Position) -> io::Result<KeyValuePair> {
+    let record = segment_of(segments, position.segment)?.read_at(position.offset)?;
+    Ok(record.kv)
+}
+
+/// Reads the key-value pair at `position`, unless it had expired by `now`.
+fn read_live(
+    segments: &Segments,
+    position: Position,
+    now: u64,
+) -> io::Result<Option<KeyValuePair>> {
+    let record = segment_of(segments, position.segment)?.read_at(position.offset)?;
+    match record.is_expired(now) {
+        true => Ok(None),
+        false => Ok(Some(record.kv)),
+    }
+}
+
+/// `true` if the record at `position` had expired by `now`. A record whose
+/// expiry time cannot be read is not.
+fn is_expired_at(segments: &Segments, position: Position, now: u64) -> bool {
+    segment_of(segments, position.segment)
+        .and_then(|segment| segment.expires_at(position.offset))
+        .is_ok_and(|expires_at| expires_at.is_some_and(|at| at <= now))
+}
+
+/// The keys of the index `entries` whose records had not expired by `now`.
+fn live_keys<'a>(
+    segments: &'a Segments,
+    entries: impl Iterator<Item = (&'a ByteString, &'a Position)>,
+    now: u64,
+) -> impl Iterator<Item = &'a ByteStr> {
+    entries
+        .filter(move |&(_, &position)| !is_expired_at(segments, position, now))
+        .map(|(key, _)| key.as_slice())
+}
+
+fn compaction_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".compact");
+    PathBuf::from(name)
+}
+
+/// Makes a rename inside `path`'s directory durable.
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => sync_dir(dir),
+        _ => sync_dir(Path::new(".")),
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
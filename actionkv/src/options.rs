@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use crate::{Compression, DEFAULT_MAX_SEGMENT_SIZE};
+
+/// When [`ActionKV`](crate::ActionKV) asks the operating system to put
+/// appended records on disk.
+///
+/// Every write reaches the operating system before it returns, so a crash of
+/// the process alone loses nothing. The policy decides how much a power
+/// failure or kernel crash can take with it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Sync after every write, batch or [`insert_all`](crate::ActionKV::insert_all)
+    /// before returning.
+    #[default]
+    Always,
+    /// Sync after every `n`th write; up to `n - 1` writes can be lost.
+    EveryN(u32),
+    /// Sync on the first write at least this long after the previous sync.
+    /// There is no background thread, so the tail of an idle store stays
+    /// unsynced until the next write or an explicit
+    /// [`sync`](crate::ActionKV::sync).
+    Interval(Duration),
+    /// Only sync when asked to, when rolling over to a new segment, when
+    /// compacting and when saving the hint file.
+    Never,
+}
+
+/// Settings for [`ActionKV::open_with`](crate::ActionKV::open_with).
+///
+/// ```no_run
+/// # use std::{path::Path, time::Duration};
+/// # use libactionkv::{ActionKV, OpenOptions, SyncPolicy};
+/// let store = ActionKV::open_with(
+///     Path::new("store"),
+///     OpenOptions {
+///         sync: SyncPolicy::Interval(Duration::from_millis(100)),
+///         ..OpenOptions::default()
+///     },
+/// )?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenOptions {
+    pub sync: SyncPolicy,
+    /// See [`ActionKV::set_max_segment_size`](crate::ActionKV::set_max_segment_size).
+    pub max_segment_size: u64,
+    /// See [`ActionKV::set_compression`](crate::ActionKV::set_compression).
+    pub compression: Compression,
+    /// See [`ActionKV::set_cache_size`](crate::ActionKV::set_cache_size).
+    /// Off by default.
+    pub cache_size: usize,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        OpenOptions {
+            sync: SyncPolicy::default(),
+            max_segment_size: DEFAULT_MAX_SEGMENT_SIZE,
+            compression: Compression::default(),
+            cache_size: 0,
+        }
+    }
+}
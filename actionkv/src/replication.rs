@@ -0,0 +1,373 @@
+//! Leader/follower replication by shipping the log.
+//!
+//! A follower connects to the leader and says how far into the leader's log
+//! it has got. The leader then follows its log from there with a [`Tail`]
+//! and sends every complete record, or every committed batch, byte for byte
+//! as it is on disk. The follower checks each record's checksum and writes
+//! it to its own store, so it can serve reads of everything it has been
+//! sent.
+//!
+//! ```text
+//! follower: MAGIC | version u32 | segment u32 | offset u64
+//! leader:   MAGIC | status u8 (0 ok, 1 error) | [len u32 | message]
+//! leader:   HEARTBEAT u8
+//!         | RECORDS u8 | segment u32 | start u64 | end u64 | format u32
+//!           | len u32 | raw records
+//! ```
+//!
+//! Positions are offsets into the leader's segments, which compaction
+//! rewrites. Compacting the leader invalidates every follower's position;
+//! start followers afresh from a snapshot after compacting a leader.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Cursor, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{ActionKV, CKSUM, Position, RecordKind, SharedKV, Tail, WriteBatch, sync_parent_dir};
+
+const MAGIC: [u8; 4] = *b"\x89AKR";
+const VERSION: u32 = 1;
+
+const OK: u8 = 0;
+const ERROR: u8 = 1;
+
+const HEARTBEAT: u8 = 0;
+const RECORDS: u8 = 1;
+
+/// How long the leader waits between looks at its log when it is idle.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How often an idle leader tells followers it is still there.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a follower waits for anything from the leader before giving up
+/// on the connection.
+const LEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a follower sends when it has nothing yet.
+const START: Position = Position {
+    segment: 0,
+    offset: 0,
+};
+
+/// Ships a [`SharedKV`]'s log to followers.
+///
+/// ```no_run
+/// # use libactionkv::{SharedKV, replication::Leader};
+/// let store = SharedKV::open("store".as_ref())?;
+/// let leader = Leader::bind("127.0.0.1:6390", store)?;
+/// leader.run()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Leader {
+    listener: TcpListener,
+    store: SharedKV,
+}
+
+impl Leader {
+    pub fn bind<A: ToSocketAddrs>(addr: A, store: SharedKV) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(Leader { listener, store })
+    }
+
+    /// The address the leader is listening on, useful after binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts followers until the listener fails.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let store = self.store.clone();
+
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(err) = ship(stream, &store) {
+                    eprintln!("follower {:?}: {}", peer, err);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Sends one follower everything after the position it asks for, and keeps
+/// sending until it hangs up.
+fn ship(stream: TcpStream, store: &SharedKV) -> io::Result<()> {
+    let mut r = BufReader::new(stream.try_clone()?);
+    let mut w = BufWriter::new(stream);
+
+    let from = read_hello(&mut r)?;
+    let from = match from {
+        START => store.start(),
+        from => from,
+    };
+
+    w.write_all(&MAGIC)?;
+    let mut tail = match store.tail(from) {
+        Ok(tail) => {
+            w.write_u8(OK)?;
+            tail
+        }
+        Err(err) => {
+            let message = err.to_string();
+            w.write_u8(ERROR)?;
+            w.write_u32::<LittleEndian>(message.len() as u32)?;
+            w.write_all(message.as_bytes())?;
+            w.flush()?;
+            return Err(err);
+        }
+    };
+    w.flush()?;
+
+    let mut idle_since = Instant::now();
+    loop {
+        match tail.next_unit()? {
+            Some(unit) => {
+                let raw = tail.raw(&unit)?;
+                w.write_u8(RECORDS)?;
+                w.write_u32::<LittleEndian>(unit.start.segment)?;
+                w.write_u64::<LittleEndian>(unit.start.offset)?;
+                w.write_u64::<LittleEndian>(unit.end)?;
+                w.write_u32::<LittleEndian>(unit.version)?;
+                w.write_u32::<LittleEndian>(raw.len() as u32)?;
+                w.write_all(&raw)?;
+                idle_since = Instant::now();
+            }
+            None => {
+                if idle_since.elapsed() >= HEARTBEAT_INTERVAL {
+                    w.write_u8(HEARTBEAT)?;
+                    idle_since = Instant::now();
+                }
+                // a follower that is caught up should not wait for a full buffer
+                w.flush()?;
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+fn read_hello<R: Read>(r: &mut R) -> io::Result<Position> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    let version = r.read_u32::<LittleEndian>()?;
+    if magic != MAGIC || version != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an actionkv follower, or one speaking another version",
+        ));
+    }
+
+    Ok(Position {
+        segment: r.read_u32::<LittleEndian>()?,
+        offset: r.read_u64::<LittleEndian>()?,
+    })
+}
+
+/// Copies a leader's log into a local [`SharedKV`].
+///
+/// The follower remembers how far it has got in a small file next to the
+/// store (`replica` in a store directory, `FILE.replica` for a single
+/// file), so it picks up where it left off after a restart. Records are
+/// only ever written after the ones before them, so if that file lags
+/// behind the store, records are written a second time with the same
+/// result.
+///
+/// Writes made to the follower's store other than through the follower are
+/// not sent anywhere, and the next record from the leader may overwrite
+/// them.
+#[derive(Debug)]
+pub struct Follower {
+    store: SharedKV,
+    path: PathBuf,
+    /// How far into the leader's log has been applied.
+    position: Position,
+}
+
+impl Follower {
+    /// Follows into `store`, starting from wherever an earlier follower of
+    /// the same store got to.
+    pub fn new(store: SharedKV) -> io::Result<Self> {
+        let path = store.replica_path();
+        let position = read_position(&path)?.unwrap_or(START);
+
+        Ok(Follower {
+            store,
+            path,
+            position,
+        })
+    }
+
+    /// How far into the leader's log has been applied.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Connects to the leader at `addr` and applies what it sends until the
+    /// connection fails. Callers usually call this in a loop, waiting a bit
+    /// between attempts.
+    pub fn run<A: ToSocketAddrs>(&mut self, addr: A) -> io::Result<()> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(LEADER_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let mut r = BufReader::new(stream.try_clone()?);
+        let mut w = BufWriter::new(stream);
+
+        w.write_all(&MAGIC)?;
+        w.write_u32::<LittleEndian>(VERSION)?;
+        w.write_u32::<LittleEndian>(self.position.segment)?;
+        w.write_u64::<LittleEndian>(self.position.offset)?;
+        w.flush()?;
+
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an actionkv leader",
+            ));
+        }
+        if r.read_u8()? != OK {
+            let len = r.read_u32::<LittleEndian>()?;
+            let mut message = vec![0; len as usize];
+            r.read_exact(&mut message)?;
+            return Err(io::Error::other(format!(
+                "leader refused: {}",
+                String::from_utf8_lossy(&message)
+            )));
+        }
+
+        loop {
+            match r.read_u8()? {
+                HEARTBEAT => {}
+                RECORDS => self.apply_frame(&mut r)?,
+                kind => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unknown frame {}", kind),
+                    ));
+                }
+            }
+
+            // save the position once the backlog has been worked through,
+            // rather than after every record
+            if r.buffer().is_empty() {
+                self.save_position()?;
+            }
+        }
+    }
+
+    fn apply_frame<R: Read>(&mut self, r: &mut R) -> io::Result<()> {
+        let start = Position {
+            segment: r.read_u32::<LittleEndian>()?,
+            offset: r.read_u64::<LittleEndian>()?,
+        };
+        let end = r.read_u64::<LittleEndian>()?;
+        let version = r.read_u32::<LittleEndian>()?;
+        let len = r.read_u32::<LittleEndian>()?;
+        if end.checked_sub(start.offset) != Some(len as u64) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("frame at {:?} is {} bytes but ends at {}", start, len, end),
+            ));
+        }
+        let mut raw = vec![0; len as usize];
+        r.read_exact(&mut raw)?;
+
+        // every record is checked against its checksum before any is applied
+        let mut records = Vec::new();
+        let mut f = Cursor::new(raw.as_slice());
+        while (f.position() as usize) < raw.len() {
+            let position = Position {
+                segment: start.segment,
+                offset: start.offset + f.position(),
+            };
+            let record = ActionKV::process_record(&mut f, version, position)?;
+            if matches!(record.kind, RecordKind::Put | RecordKind::Delete) {
+                records.push(record);
+            }
+        }
+
+        match records.as_slice() {
+            [record] if record.kind == RecordKind::Put => {
+                self.store
+                    .put(&record.kv.key, &record.kv.value, record.expires_at)?
+            }
+            [record] => self.store.delete(&record.kv.key)?,
+            _ => {
+                let mut batch = WriteBatch::new();
+                for record in &records {
+                    match record.kind {
+                        RecordKind::Put => batch.put(&record.kv.key, &record.kv.value),
+                        _ => batch.delete(&record.kv.key),
+                    };
+                }
+                self.store.write(batch)?;
+            }
+        }
+
+        self.position = Position {
+            segment: start.segment,
+            offset: end,
+        };
+        Ok(())
+    }
+
+    /// Puts the applied records on disk, then records how far they go.
+    fn save_position(&self) -> io::Result<()> {
+        self.store.sync()?;
+
+        let mut body = Vec::with_capacity(16);
+        body.write_u32::<LittleEndian>(self.position.segment)?;
+        body.write_u64::<LittleEndian>(self.position.offset)?;
+        let checksum = CKSUM.checksum(&body);
+        body.write_u32::<LittleEndian>(checksum)?;
+
+        let mut tmp_name = OsString::from(self.path.as_os_str());
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&body)?;
+        tmp.sync_all()?;
+        drop(tmp);
+
+        // a position that went back after a power failure would replay
+        // records the store already holds
+        fs::rename(&tmp_path, &self.path)?;
+        sync_parent_dir(&self.path)
+    }
+}
+
+/// Reads the position saved by [`Follower::save_position`], if there is a
+/// good one.
+fn read_position(path: &Path) -> io::Result<Option<Position>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    if bytes.len() != 16 {
+        return Ok(None);
+    }
+
+    let mut f = Cursor::new(&bytes);
+    let position = Position {
+        segment: f.read_u32::<LittleEndian>()?,
+        offset: f.read_u64::<LittleEndian>()?,
+    };
+    match f.read_u32::<LittleEndian>()? == CKSUM.checksum(&bytes[..12]) {
+        true => Ok(Some(position)),
+        false => Ok(None),
+    }
+}
@@ -0,0 +1,177 @@
+//! Just enough of the Redis serialization protocol (RESP2) to serve a store.
+//!
+//! Clients send each command as an array of bulk strings,
+//! `*<n>\r\n` followed by `n` times `$<len>\r\n<bytes>\r\n`. Plain
+//! space-separated "inline" commands, as typed into `telnet`, are accepted
+//! too.
+
+use std::io::{self, BufRead, Read, Write};
+
+use crate::ByteString;
+
+/// Longest bulk string a client may send: the largest value a record can hold.
+const MAX_BULK_LEN: u64 = u32::MAX as u64;
+
+/// A reply to one command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<ByteString>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    pub fn error(message: impl Into<String>) -> Self {
+        Reply::Error(message.into())
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match self {
+            Reply::Simple(s) => write!(w, "+{}\r\n", s),
+            // a newline would end the error early
+            Reply::Error(message) => write!(w, "-{}\r\n", message.replace(['\r', '\n'], " ")),
+            Reply::Integer(n) => write!(w, ":{}\r\n", n),
+            Reply::Bulk(None) => w.write_all(b"$-1\r\n"),
+            Reply::Bulk(Some(bytes)) => {
+                write!(w, "${}\r\n", bytes.len())?;
+                w.write_all(bytes)?;
+                w.write_all(b"\r\n")
+            }
+            Reply::Array(items) => {
+                write!(w, "*{}\r\n", items.len())?;
+                for item in items {
+                    item.write_to(w)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reads the next command, or `None` once the client has hung up.
+///
+/// Malformed input is returned as an [`io::ErrorKind::InvalidData`] error,
+/// after which the connection cannot be trusted to be in step.
+pub(crate) fn read_command<R: BufRead>(r: &mut R) -> io::Result<Option<Vec<ByteString>>> {
+    loop {
+        let line = match read_line(r)? {
+            None => return Ok(None),
+            Some(line) => line,
+        };
+
+        let Some(count) = line.strip_prefix(b"*") else {
+            let args: Vec<ByteString> = line
+                .split(|byte| byte.is_ascii_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect();
+
+            // blank lines are allowed between inline commands
+            if args.is_empty() {
+                continue;
+            }
+            return Ok(Some(args));
+        };
+
+        let count = parse_len(count, "multibulk length")?;
+        let mut args = Vec::with_capacity(count.min(64) as usize);
+        for _ in 0..count {
+            args.push(read_bulk(r)?);
+        }
+
+        return Ok(Some(args));
+    }
+}
+
+fn read_bulk<R: BufRead>(r: &mut R) -> io::Result<ByteString> {
+    let line = read_line(r)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+    let len = match line.strip_prefix(b"$") {
+        Some(len) => parse_len(len, "bulk length")?,
+        None => return Err(protocol_error("expected '$'")),
+    };
+    if len > MAX_BULK_LEN {
+        return Err(protocol_error("invalid bulk length"));
+    }
+
+    let mut bytes = ByteString::new();
+    r.by_ref().take(len + 2).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len + 2 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if !bytes.ends_with(b"\r\n") {
+        return Err(protocol_error("bulk string not followed by CRLF"));
+    }
+    bytes.truncate(len as usize);
+
+    Ok(bytes)
+}
+
+/// Reads one line, without its line ending.
+fn read_line<R: BufRead>(r: &mut R) -> io::Result<Option<ByteString>> {
+    let mut line = ByteString::new();
+    if r.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+
+    if line.pop() != Some(b'\n') {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    Ok(Some(line))
+}
+
+fn parse_len(digits: &[u8], what: &str) -> io::Result<u64> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(|| protocol_error(&format!("invalid {}", what)))
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Protocol error: {}", message),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Reply, read_command};
+
+    #[test]
+    fn test_read_command() {
+        let mut input: &[u8] = b"*2\r\n$3\r\nGET\r\n$5\r\nhe\r\no\r\nPING\r\n\r\nSET a  b\r\n";
+
+        let commands: Vec<Vec<Vec<u8>>> = vec![
+            vec![b"GET".to_vec(), b"he\r\no".to_vec()],
+            vec![b"PING".to_vec()],
+            vec![b"SET".to_vec(), b"a".to_vec(), b"b".to_vec()],
+        ];
+        for command in commands {
+            assert_eq!(read_command(&mut input).ok().flatten(), Some(command));
+        }
+        assert!(matches!(read_command(&mut input), Ok(None)));
+    }
+
+    #[test]
+    fn test_write_reply() {
+        let reply = Reply::Array(vec![
+            Reply::Bulk(Some(b"0".to_vec())),
+            Reply::Array(vec![Reply::Bulk(None), Reply::Integer(-2)]),
+            Reply::Simple("OK"),
+            Reply::error("ERR bad\nthing"),
+        ]);
+
+        let mut out = Vec::new();
+        assert!(reply.write_to(&mut out).is_ok());
+        assert_eq!(
+            out,
+            b"*4\r\n$1\r\n0\r\n*2\r\n$-1\r\n:-2\r\n+OK\r\n-ERR bad thing\r\n"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use std::{collections::btree_map, io, ops::Bound};
+
+use crate::{ByteStr, ByteString, KeyValuePair, Position, segment::Segments};
+
+/// Iterator over a range of keys in key order, returned by
+/// [`ActionKV::scan`](crate::ActionKV::scan) and
+/// [`ActionKV::scan_prefix`](crate::ActionKV::scan_prefix).
+///
+/// Each value is read from disk as the iterator reaches it; keys that have
+/// expired are skipped.
+pub struct Scan<'a> {
+    segments: &'a Segments,
+    positions: btree_map::Range<'a, ByteString, Position>,
+    /// Records that expired before this time are skipped.
+    now: u64,
+}
+
+impl<'a> Scan<'a> {
+    pub(crate) fn new(
+        segments: &'a Segments,
+        positions: btree_map::Range<'a, ByteString, Position>,
+        now: u64,
+    ) -> Self {
+        Scan {
+            segments,
+            positions,
+            now,
+        }
+    }
+}
+
+impl Iterator for Scan<'_> {
+    type Item = io::Result<KeyValuePair>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (_, &position) = self.positions.next()?;
+            match crate::read_live(self.segments, position, self.now) {
+                Ok(None) => continue,
+                Ok(Some(kv)) => return Some(Ok(kv)),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.positions.size_hint().1)
+    }
+}
+
+/// The exclusive upper bound of all keys that start with `prefix`.
+fn prefix_end(prefix: &ByteStr) -> Bound<ByteString> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Bound::Excluded(end);
+        }
+    }
+
+    Bound::Unbounded
+}
+
+/// The `(start, end)` bounds of all keys that start with `prefix`.
+pub(crate) fn prefix_range(prefix: &ByteStr) -> (Bound<ByteString>, Bound<ByteString>) {
+    (Bound::Included(prefix.to_vec()), prefix_end(prefix))
+}
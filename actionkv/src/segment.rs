@@ -0,0 +1,285 @@
+//! Segment files: the numbered pieces a store's log is split into.
+//!
+//! Each segment starts with its own `MAGIC | version` header, so every file
+//! in a store can be read on its own. Only the newest segment is appended
+//! to; the rest are sealed and only change when they are compacted.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{ActionKV, FORMAT_VERSION, Position, Record};
+
+/// Marks the start of a versioned log. Files written before the format was
+/// versioned have no header and are read as version 0.
+const MAGIC: [u8; 4] = *b"\x89AKV";
+
+/// Size of the `MAGIC | version` file header.
+pub(crate) const HEADER_LEN: u64 = 8;
+
+/// File name extension of a segment inside a store directory.
+const EXTENSION: &str = "akv";
+
+pub(crate) type Segments = BTreeMap<u32, Segment>;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Segment {
+    pub id: u32,
+    pub path: PathBuf,
+    /// Opened for reading, and for appending unless opened with
+    /// [`Segment::open_read`]. Reads go through [`ReadAt`], so the
+    /// handle can be shared without anyone moving its cursor.
+    pub file: Arc<File>,
+    pub version: u32,
+}
+
+impl Segment {
+    /// Opens the segment at `path`, creating it with a fresh header if needed.
+    pub fn open(id: u32, path: &Path) -> io::Result<Self> {
+        let mut f = OpenOptions::new()
+            .read(true)
+            // .write(true)
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let version = Segment::read_header(&mut f)?;
+
+        Ok(Segment {
+            id,
+            path: path.to_path_buf(),
+            file: Arc::new(f),
+            version,
+        })
+    }
+
+    /// Opens the segment at `path` for reading only. Unlike
+    /// [`Segment::open`] this never creates the file or writes to it, so it
+    /// works on a store the caller cannot write and never races the writer.
+    ///
+    /// A file too short to hold a header is taken to be a segment whose
+    /// header the writer has not finished yet.
+    pub fn open_read(id: u32, path: &Path) -> io::Result<Self> {
+        let mut f = OpenOptions::new().read(true).open(path)?;
+        let version = match f.metadata()?.len() {
+            len if len < HEADER_LEN => FORMAT_VERSION,
+            _ => Segment::parse_header(&mut f)?,
+        };
+
+        Ok(Segment {
+            id,
+            path: path.to_path_buf(),
+            file: Arc::new(f),
+            version,
+        })
+    }
+
+    /// Returns the format version of the file, writing a fresh header if the
+    /// file is empty.
+    fn read_header(f: &mut File) -> io::Result<u32> {
+        if f.metadata()?.len() == 0 {
+            Segment::write_header(f)?;
+            return Ok(FORMAT_VERSION);
+        }
+
+        Segment::parse_header(f)
+    }
+
+    /// Returns the format version named by the header of a non-empty file.
+    fn parse_header(f: &mut File) -> io::Result<u32> {
+        let mut magic = [0; 4];
+        f.seek(SeekFrom::Start(0))?;
+        match f.read_exact(&mut magic) {
+            Ok(()) if magic == MAGIC => {}
+            Ok(()) => return Ok(0),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(0),
+            Err(err) => return Err(err),
+        }
+
+        let version = f.read_u32::<LittleEndian>()?;
+        if version > FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "log format version {} is newer than {}",
+                    version, FORMAT_VERSION
+                ),
+            ));
+        }
+
+        Ok(version)
+    }
+
+    pub fn write_header<W: Write>(f: &mut W) -> io::Result<()> {
+        f.write_all(&MAGIC)?;
+        f.write_u32::<LittleEndian>(FORMAT_VERSION)
+    }
+
+    /// Rewrites the header of a version 1 segment to claim the current
+    /// version, whose records are a superset of version 1's.
+    pub fn bump_version(&mut self) -> io::Result<()> {
+        debug_assert!(self.version >= 1);
+
+        let mut f = OpenOptions::new().write(true).open(&self.path)?;
+        Segment::write_header(&mut f)?;
+        f.sync_data()?;
+        self.version = FORMAT_VERSION;
+
+        Ok(())
+    }
+
+    /// Offset of the first record.
+    pub fn data_start(&self) -> u64 {
+        if self.version == 0 { 0 } else { HEADER_LEN }
+    }
+
+    pub fn len(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    /// A buffered reader positioned at `offset`.
+    pub fn reader_at(&self, offset: u64) -> io::Result<BufReader<ReadAt<'_>>> {
+        let mut f = BufReader::new(ReadAt::new(&self.file));
+        f.seek(SeekFrom::Start(offset))?;
+        Ok(f)
+    }
+
+    /// The kind byte of the record at `offset`, or `0` if there is none.
+    pub fn read_kind_at(&self, offset: u64) -> io::Result<u8> {
+        if self.version == 0 {
+            return Ok(0);
+        }
+
+        let mut kind = [0];
+        let mut f = self.reader_at(offset + 4)?;
+        match f.read_exact(&mut kind) {
+            Err(err) if err.kind() != io::ErrorKind::UnexpectedEof => Err(err),
+            _ => Ok(kind[0]),
+        }
+    }
+
+    /// When the record at `offset` expires, read from its header without
+    /// checking the record.
+    pub fn expires_at(&self, offset: u64) -> io::Result<Option<u64>> {
+        let kind = self.read_kind_at(offset)?;
+        if ActionKV::expiry_len(self.version, kind) == 0 {
+            return Ok(None);
+        }
+
+        let mut f = self.reader_at(offset + 13)?;
+        f.read_u64::<LittleEndian>().map(Some)
+    }
+
+    pub fn read_at(&self, offset: u64) -> io::Result<Record> {
+        let mut f = self.reader_at(offset)?;
+        ActionKV::process_record(
+            &mut f,
+            self.version,
+            Position {
+                segment: self.id,
+                offset,
+            },
+        )
+    }
+}
+
+/// Path of segment `id` inside the store directory `dir`.
+pub(crate) fn path_in(dir: &Path, id: u32) -> PathBuf {
+    dir.join(format!("{:08}.{}", id, EXTENSION))
+}
+
+/// Opens every segment in the store directory `dir`.
+pub(crate) fn open_dir(dir: &Path) -> io::Result<Segments> {
+    let mut segments = Segments::new();
+
+    for (id, path) in paths_in(dir)? {
+        segments.insert(id, Segment::open(id, &path)?);
+    }
+
+    Ok(segments)
+}
+
+/// Like [`open_dir`], but opens every segment with [`Segment::open_read`].
+pub(crate) fn open_dir_read(dir: &Path) -> io::Result<Segments> {
+    let mut segments = Segments::new();
+
+    for (id, path) in paths_in(dir)? {
+        segments.insert(id, Segment::open_read(id, &path)?);
+    }
+
+    Ok(segments)
+}
+
+/// Paths of the segments in the store directory `dir` by id, without
+/// opening them.
+pub(crate) fn paths_in(dir: &Path) -> io::Result<BTreeMap<u32, PathBuf>> {
+    let mut paths = BTreeMap::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let id: Option<u32> = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(EXTENSION)?.strip_suffix('.'))
+            .filter(|stem| stem.bytes().all(|byte| byte.is_ascii_digit()))
+            .and_then(|stem| stem.parse().ok());
+
+        if let Some(id) = id {
+            paths.insert(id, path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Reads a shared `File` through positional reads, keeping its own cursor.
+pub(crate) struct ReadAt<'a> {
+    file: &'a File,
+    position: u64,
+}
+
+impl<'a> ReadAt<'a> {
+    fn new(file: &'a File) -> Self {
+        ReadAt { file, position: 0 }
+    }
+}
+
+impl Read for ReadAt<'_> {
+    #[cfg(unix)]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        use std::os::unix::fs::FileExt;
+
+        let n = self.file.read_at(buf, self.position)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    #[cfg(windows)]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        use std::os::windows::fs::FileExt;
+
+        let n = self.file.seek_read(buf, self.position)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for ReadAt<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.file.metadata()?.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative offset")
+        })?;
+        Ok(self.position)
+    }
+}
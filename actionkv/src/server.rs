@@ -0,0 +1,325 @@
+//! A TCP front end that serves a store over a subset of the Redis protocol.
+//!
+//! Supported commands: `PING`, `GET`, `SET key value [EX seconds | PX
+//! milliseconds]`, `DEL`, `EXISTS` and `SCAN cursor [MATCH pattern] [COUNT
+//! count]`. Each connection gets its own
+//! thread; they all share one [`SharedKV`], so reads run in parallel and
+//! writes are appended one at a time.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufReader, BufWriter, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    ops::Bound,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    ByteStr, ByteString, SharedKV,
+    resp::{self, Reply},
+};
+
+/// Keys returned by one `SCAN` call when the client does not ask for a `COUNT`.
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// Scan cursors one connection keeps before forgetting the oldest.
+const MAX_CURSORS: usize = 64;
+
+/// Serves a [`SharedKV`] to Redis clients.
+///
+/// ```no_run
+/// # use libactionkv::{SharedKV, server::Server};
+/// let store = SharedKV::open("store".as_ref())?;
+/// let server = Server::bind("127.0.0.1:6380", store)?;
+/// server.run()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    store: SharedKV,
+    read_only: bool,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, store: SharedKV) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(Server {
+            listener,
+            store,
+            read_only: false,
+        })
+    }
+
+    /// Refuses `SET` and `DEL`, as a replica does.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// The address the server is listening on, useful after binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections until the listener fails.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let store = self.store.clone();
+            let read_only = self.read_only;
+
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(err) = serve(stream, &store, read_only) {
+                    eprintln!("connection {:?}: {}", peer, err);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Answers the commands on one connection until the client hangs up.
+fn serve(stream: TcpStream, store: &SharedKV, read_only: bool) -> io::Result<()> {
+    let mut r = BufReader::new(stream.try_clone()?);
+    let mut w = BufWriter::new(stream);
+    let mut cursors = Cursors::default();
+
+    loop {
+        let command = match resp::read_command(&mut r) {
+            Ok(Some(command)) => command,
+            Ok(None) => return Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                // the stream is out of step, so tell the client and hang up
+                Reply::error(format!("ERR {}", err)).write_to(&mut w)?;
+                return w.flush();
+            }
+            Err(err) => return Err(err),
+        };
+
+        let reply = match command.first() {
+            Some(name) if read_only && is_write(name) => {
+                Reply::error("READONLY You can't write against a read only replica.")
+            }
+            _ => execute(store, &command, &mut cursors)
+                .unwrap_or_else(|err| Reply::error(format!("ERR {}", err))),
+        };
+        reply.write_to(&mut w)?;
+
+        // pipelined commands are answered together
+        if r.buffer().is_empty() {
+            w.flush()?;
+        }
+    }
+}
+
+fn execute(store: &SharedKV, command: &[ByteString], cursors: &mut Cursors) -> io::Result<Reply> {
+    let Some((name, args)) = command.split_first() else {
+        return Ok(Reply::error("ERR empty command"));
+    };
+    let name = String::from_utf8_lossy(name).to_ascii_uppercase();
+
+    let reply = match (name.as_str(), args) {
+        ("PING", []) => Reply::Simple("PONG"),
+        ("PING", [message]) => Reply::Bulk(Some(message.clone())),
+
+        ("GET", [key]) => Reply::Bulk(store.get(key)?),
+
+        ("SET", [key, value]) => {
+            store.insert(key, value)?;
+            Reply::Simple("OK")
+        }
+
+        ("SET", [key, value, unit, amount]) => {
+            let ttl = match parse_number(amount) {
+                Some(n) if n > 0 && unit.eq_ignore_ascii_case(b"EX") => {
+                    Duration::from_secs(n as u64)
+                }
+                Some(n) if n > 0 && unit.eq_ignore_ascii_case(b"PX") => {
+                    Duration::from_millis(n as u64)
+                }
+                Some(_) if unit.eq_ignore_ascii_case(b"EX") || unit.eq_ignore_ascii_case(b"PX") => {
+                    return Ok(Reply::error("ERR invalid expire time in 'set' command"));
+                }
+                _ => return Ok(Reply::error("ERR syntax error")),
+            };
+
+            store.insert_with_ttl(key, value, ttl)?;
+            Reply::Simple("OK")
+        }
+
+        ("DEL", keys) if !keys.is_empty() => {
+            let mut deleted = 0;
+            for key in keys {
+                // deleting a missing key would only add a tombstone
+                if store.contains_key(key) {
+                    store.delete(key)?;
+                    deleted += 1;
+                }
+            }
+            Reply::Integer(deleted)
+        }
+
+        ("EXISTS", keys) if !keys.is_empty() => {
+            let mut found = 0;
+            for key in keys {
+                if store.contains_key(key) {
+                    found += 1;
+                }
+            }
+            Reply::Integer(found)
+        }
+
+        ("SCAN", [cursor, options @ ..]) => scan(store, cursors, cursor, options),
+
+        ("PING" | "GET" | "SET" | "DEL" | "EXISTS" | "SCAN", _) => Reply::error(format!(
+            "ERR wrong number of arguments for '{}' command",
+            name.to_ascii_lowercase()
+        )),
+
+        _ => Reply::error(format!(
+            "ERR unknown command '{}'",
+            name.to_ascii_lowercase()
+        )),
+    };
+
+    Ok(reply)
+}
+
+fn is_write(name: &ByteStr) -> bool {
+    name.eq_ignore_ascii_case(b"SET") || name.eq_ignore_ascii_case(b"DEL")
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count]`.
+///
+/// Keys are visited in order, and each call carries on after the last key
+/// visited by the call that handed out its cursor. As with Redis, a key
+/// that exists for the whole scan is returned exactly once; keys inserted
+/// or deleted during the scan may or may not be.
+fn scan(
+    store: &SharedKV,
+    cursors: &mut Cursors,
+    cursor: &ByteStr,
+    options: &[ByteString],
+) -> Reply {
+    let after = match parse_number(cursor) {
+        Some(0) => None,
+        Some(cursor) => match cursors.last_keys.get(&cursor) {
+            Some(key) => Some(key.clone()),
+            None => return Reply::error("ERR invalid cursor"),
+        },
+        None => return Reply::error("ERR invalid cursor"),
+    };
+
+    let mut pattern: Option<&ByteStr> = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    for option in options.chunks(2) {
+        match option {
+            [name, value] if name.eq_ignore_ascii_case(b"MATCH") => pattern = Some(value),
+            [name, value] if name.eq_ignore_ascii_case(b"COUNT") => match parse_number(value) {
+                Some(n) if n > 0 => count = n,
+                _ => return Reply::error("ERR value is not an integer or out of range"),
+            },
+            _ => return Reply::error("ERR syntax error"),
+        }
+    }
+
+    let snapshot = store.snapshot();
+    let start = match &after {
+        Some(key) => Bound::Excluded(key.as_slice()),
+        None => Bound::Unbounded,
+    };
+    let mut keys = snapshot.keys_in((start, Bound::Unbounded));
+    let visited: Vec<&ByteStr> = keys.by_ref().take(count).collect();
+    let next = match (keys.next(), visited.last()) {
+        (Some(_), Some(last)) => cursors.add(last.to_vec()),
+        _ => 0,
+    };
+
+    let found = visited
+        .into_iter()
+        .filter(|key| pattern.is_none_or(|pattern| glob_match(pattern, key)))
+        .map(|key| Reply::Bulk(Some(key.to_vec())))
+        .collect();
+
+    Reply::Array(vec![
+        Reply::Bulk(Some(next.to_string().into_bytes())),
+        Reply::Array(found),
+    ])
+}
+
+/// The scans under way on one connection. A cursor stands for the last key
+/// returned by the call that handed it out, so resuming from it is a range
+/// lookup rather than a walk over the keys already visited.
+#[derive(Debug, Default)]
+struct Cursors {
+    last_keys: BTreeMap<usize, ByteString>,
+    /// The last cursor handed out. `0` is never one; it starts a new scan.
+    last_id: usize,
+}
+
+impl Cursors {
+    /// Hands out a cursor that resumes after `last_key`.
+    fn add(&mut self, last_key: ByteString) -> usize {
+        if self.last_keys.len() >= MAX_CURSORS {
+            self.last_keys.pop_first();
+        }
+        self.last_id = self.last_id.wrapping_add(1).max(1);
+        self.last_keys.insert(self.last_id, last_key);
+
+        self.last_id
+    }
+}
+
+fn parse_number(bytes: &ByteStr) -> Option<usize> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Matches `key` against a Redis-style glob: `*` matches any run of bytes,
+/// `?` any single byte and `\` escapes the byte after it.
+fn glob_match(pattern: &ByteStr, key: &ByteStr) -> bool {
+    let (mut p, mut k) = (0, 0);
+    // where the last `*` was, and how much of `key` it has swallowed so far
+    let mut star: Option<(usize, usize)> = None;
+
+    while k < key.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, k));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => {
+                p += 1;
+                k += 1;
+                continue;
+            }
+            Some(b'\\') if pattern.get(p + 1) == Some(&key[k]) => {
+                p += 2;
+                k += 1;
+                continue;
+            }
+            Some(&byte) if byte != b'\\' && byte == key[k] => {
+                p += 1;
+                k += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        // backtrack: let the last `*` swallow one more byte
+        match star {
+            Some((star_p, star_k)) => {
+                star = Some((star_p, star_k + 1));
+                p = star_p + 1;
+                k = star_k + 1;
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
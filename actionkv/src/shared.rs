@@ -0,0 +1,375 @@
+//! A thread-safe handle to a store: many readers, one writer.
+//!
+//! Reads never move a shared file cursor. They look up the record's position
+//! under a short read lock and then fetch it with a positional read, so any
+//! number of threads can read at once. Writes are serialised through a
+//! single [`ActionKV`] that appends to the log, and only take the index's
+//! write lock for the moment it takes to publish the new offsets.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    ops::RangeBounds,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, mpsc::Receiver},
+    time::Duration,
+};
+
+use crate::{
+    ActionKV, ByteStr, ByteString, Change, Clock, FORMAT_VERSION, KeyValuePair, Position,
+    RecordKind, Tail, WriteBatch, backup, clock, hint,
+    scan::{self, Scan},
+    segment::Segments,
+};
+
+/// A `Send + Sync` handle to a store, cheap to clone and share between threads.
+///
+/// ```no_run
+/// # use libactionkv::SharedKV;
+/// # use std::thread;
+/// let store = SharedKV::open("store".as_ref())?;
+///
+/// let reader = store.clone();
+/// let handle = thread::spawn(move || reader.get(b"greeting"));
+///
+/// store.insert(b"greeting", b"hello")?;
+/// let _maybe_old = handle.join().unwrap()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedKV {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: RwLock<State>,
+    /// The single appender. Its own `index` stays empty: the live index is
+    /// the one in `state`.
+    writer: Mutex<ActionKV>,
+    /// The writer's clock, for readers to tell which keys have expired.
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug)]
+struct State {
+    segments: Arc<Segments>,
+    index: Arc<BTreeMap<ByteString, Position>>,
+}
+
+/// A frozen view of the store, unaffected by writes made after it was taken.
+///
+/// Taking a snapshot is cheap; the first write after it copies the index.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    segments: Arc<Segments>,
+    index: Arc<BTreeMap<ByteString, Position>>,
+    /// Keys are judged expired or not as of when the snapshot was taken.
+    now: u64,
+}
+
+impl SharedKV {
+    /// Opens and loads the log at `path`.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut store = ActionKV::open(path)?;
+        store.load()?;
+
+        SharedKV::new(store)
+    }
+
+    /// Shares an already loaded `store`.
+    pub fn new(mut store: ActionKV) -> io::Result<Self> {
+        // the writer cannot upgrade a version 0 log without its index
+        if store.version() < FORMAT_VERSION {
+            store.compact()?;
+        }
+
+        let state = State {
+            segments: Arc::new(store.segments.clone()),
+            index: Arc::new(std::mem::take(&mut store.index)),
+        };
+
+        Ok(SharedKV {
+            inner: Arc::new(Inner {
+                state: RwLock::new(state),
+                clock: Arc::clone(&store.clock),
+                writer: Mutex::new(store),
+            }),
+        })
+    }
+
+    pub fn get(&self, key: &ByteStr) -> io::Result<Option<ByteString>> {
+        let (segments, position) = {
+            let state = self.state();
+            match state.index.get(key) {
+                None => return Ok(None),
+                Some(&position) => (Arc::clone(&state.segments), position),
+            }
+        };
+
+        let now = clock::millis(&*self.inner.clock);
+        let kv = crate::read_live(&segments, position, now)?;
+        Ok(kv.map(|kv| kv.value))
+    }
+
+    /// `true` if `key` is live. Like [`ActionKV::keys`], this only reads the
+    /// expiry time from the record's header.
+    pub fn contains_key(&self, key: &ByteStr) -> bool {
+        let (segments, position) = {
+            let state = self.state();
+            match state.index.get(key) {
+                None => return false,
+                Some(&position) => (Arc::clone(&state.segments), position),
+            }
+        };
+
+        let now = clock::millis(&*self.inner.clock);
+        !crate::is_expired_at(&segments, position, now)
+    }
+
+    /// A consistent view of every key and value at this moment.
+    pub fn snapshot(&self) -> Snapshot {
+        let state = self.state();
+        Snapshot {
+            segments: Arc::clone(&state.segments),
+            index: Arc::clone(&state.index),
+            now: clock::millis(&*self.inner.clock),
+        }
+    }
+
+    pub fn insert(&self, key: &ByteStr, value: &ByteStr) -> io::Result<()> {
+        self.put(key, value, None)
+    }
+
+    /// See [`ActionKV::insert_with_ttl`].
+    pub fn insert_with_ttl(&self, key: &ByteStr, value: &ByteStr, ttl: Duration) -> io::Result<()> {
+        let expires_at = self.writer().expiry(ttl);
+        self.put(key, value, Some(expires_at))
+    }
+
+    /// Writes `key`, expiring at `expires_at` if given, in milliseconds since
+    /// the Unix epoch.
+    pub(crate) fn put(
+        &self,
+        key: &ByteStr,
+        value: &ByteStr,
+        expires_at: Option<u64>,
+    ) -> io::Result<()> {
+        let mut writer = self.writer();
+        let position = match expires_at {
+            None => writer.insert_but_ignore_index(key, value)?,
+            Some(_) => writer.append(RecordKind::Put, key, value, expires_at)?,
+        };
+
+        let mut state = self.state_mut();
+        publish_segments(&mut state, &writer);
+        let old = Arc::make_mut(&mut state.index).insert(key.to_vec(), position);
+        writer
+            .subscribers
+            .notify(RecordKind::Put, key, old, position);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn update(&self, key: &ByteStr, value: &ByteStr) -> io::Result<()> {
+        self.insert(key, value)
+    }
+
+    pub fn delete(&self, key: &ByteStr) -> io::Result<()> {
+        let mut writer = self.writer();
+        let position = writer.append(RecordKind::Delete, key, b"", None)?;
+
+        let mut state = self.state_mut();
+        publish_segments(&mut state, &writer);
+        let old = Arc::make_mut(&mut state.index).remove(key);
+        writer
+            .subscribers
+            .notify(RecordKind::Delete, key, old, position);
+        Ok(())
+    }
+
+    /// See [`ActionKV::write`]. Readers see either none or all of `batch`.
+    pub fn write(&self, batch: WriteBatch) -> io::Result<()> {
+        let mut writer = self.writer();
+        let positions = writer.append_batch(&batch)?;
+
+        let mut state = self.state_mut();
+        publish_segments(&mut state, &writer);
+        batch.apply(
+            positions,
+            Arc::make_mut(&mut state.index),
+            &mut writer.subscribers,
+        );
+        Ok(())
+    }
+
+    /// See [`ActionKV::compact`]. Writers wait for the compaction to finish;
+    /// readers carry on against the old segments until the new ones are
+    /// swapped in.
+    pub fn compact(&self) -> io::Result<()> {
+        let mut writer = self.writer();
+        writer.index = BTreeMap::clone(&self.state().index);
+
+        let result = writer.compact();
+        let index = std::mem::take(&mut writer.index);
+        result?;
+
+        let mut state = self.state_mut();
+        state.segments = Arc::new(writer.segments.clone());
+        state.index = Arc::new(index);
+
+        Ok(())
+    }
+
+    /// See [`ActionKV::save_hint`].
+    pub fn save_hint(&self) -> io::Result<()> {
+        let mut writer = self.writer();
+        writer.check_loaded()?;
+        let covered = writer.synced_end()?;
+        let index = Arc::clone(&self.state().index);
+
+        hint::write(&writer.hint_path(), covered, &index)
+    }
+
+    /// Like [`ActionKV::snapshot`], but writers only wait while the end of
+    /// the log is recorded, not while it is copied.
+    ///
+    /// Not to be confused with [`SharedKV::snapshot`], which is an in-memory
+    /// view.
+    pub fn snapshot_to(&self, dest: &Path) -> io::Result<backup::Manifest> {
+        let (segments, covered, index) = {
+            let mut writer = self.writer();
+            let covered = writer.synced_end()?;
+            let index = Arc::clone(&self.state().index);
+            (writer.segments.clone(), covered, index)
+        };
+
+        // compaction renames new segments over old ones, but these handles
+        // keep the old files readable until the copy is done
+        backup::write(dest, &segments, covered, &index)
+    }
+
+    /// Where the oldest segment's records start.
+    pub(crate) fn start(&self) -> Position {
+        match self.state().segments.first_key_value() {
+            Some((_, segment)) => Position {
+                segment: segment.id,
+                offset: segment.data_start(),
+            },
+            None => Position {
+                segment: 0,
+                offset: 0,
+            },
+        }
+    }
+
+    pub(crate) fn replica_path(&self) -> PathBuf {
+        self.writer().replica_path()
+    }
+
+    /// See [`ActionKV::subscribe`].
+    pub fn subscribe(&self, prefix: &ByteStr) -> Receiver<Change> {
+        self.writer().subscribe(prefix)
+    }
+
+    /// See [`ActionKV::tail`].
+    pub fn tail(&self, from: Position) -> io::Result<Tail> {
+        self.writer().tail(from)
+    }
+
+    /// See [`ActionKV::flush`].
+    pub fn flush(&self) -> io::Result<()> {
+        self.writer().flush()
+    }
+
+    /// See [`ActionKV::sync`].
+    pub fn sync(&self) -> io::Result<()> {
+        self.writer().sync()
+    }
+
+    fn state(&self) -> RwLockReadGuard<'_, State> {
+        // the state is only ever replaced whole, so a panic elsewhere
+        // cannot have left it half-updated
+        self.inner
+            .state
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn state_mut(&self) -> RwLockWriteGuard<'_, State> {
+        self.inner
+            .state
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn writer(&self) -> MutexGuard<'_, ActionKV> {
+        self.inner
+            .writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Snapshot {
+    pub fn get(&self, key: &ByteStr) -> io::Result<Option<ByteString>> {
+        match self.index.get(key) {
+            None => Ok(None),
+            Some(&position) => {
+                let kv = crate::read_live(&self.segments, position, self.now)?;
+                Ok(kv.map(|kv| kv.value))
+            }
+        }
+    }
+
+    /// Number of live keys. Reads the header of every record.
+    pub fn len(&self) -> usize {
+        self.keys().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys().next().is_none()
+    }
+
+    /// All live keys in the snapshot, in order. See [`ActionKV::keys`].
+    pub fn keys(&self) -> impl Iterator<Item = &ByteStr> {
+        self.keys_in(..)
+    }
+
+    /// The live keys in `range`, in order.
+    pub fn keys_in<R>(&self, range: R) -> impl Iterator<Item = &ByteStr>
+    where
+        R: RangeBounds<ByteStr>,
+    {
+        crate::live_keys(&self.segments, self.index.range(range), self.now)
+    }
+
+    /// See [`ActionKV::scan`].
+    pub fn scan<R>(&self, range: R) -> impl Iterator<Item = io::Result<KeyValuePair>> + '_
+    where
+        R: RangeBounds<ByteStr>,
+    {
+        Scan::new(&self.segments, self.index.range(range), self.now)
+    }
+
+    /// See [`ActionKV::scan_prefix`].
+    pub fn scan_prefix(
+        &self,
+        prefix: &ByteStr,
+    ) -> impl Iterator<Item = io::Result<KeyValuePair>> + '_ {
+        Scan::new(
+            &self.segments,
+            self.index.range(scan::prefix_range(prefix)),
+            self.now,
+        )
+    }
+}
+
+/// Makes a segment the writer has just rolled over to visible to readers.
+fn publish_segments(state: &mut State, writer: &ActionKV) {
+    if state.segments.len() != writer.segments.len()
+        || state.segments.keys().last() != writer.segments.keys().last()
+    {
+        state.segments = Arc::new(writer.segments.clone());
+    }
+}
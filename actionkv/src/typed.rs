@@ -0,0 +1,207 @@
+//! A store of serde types rather than byte strings.
+//!
+//! [`TypedKV`] encodes keys and values with a [`Codec`] on the way in and
+//! decodes them on the way out, so callers never handle the bytes. Keys are
+//! looked up and ordered by their encoded form, which for most codecs is not
+//! the order of the original values.
+
+use std::{error::Error, fmt, io, marker::PhantomData, time::Duration};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{ActionKV, ByteString};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Turns values into bytes and back for a [`TypedKV`].
+pub trait Codec {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<ByteString, BoxError>;
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError>;
+}
+
+/// Compact binary encoding with [`bincode`]. The default codec.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<ByteString, BoxError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// JSON, which other tools reading the store can make sense of.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<ByteString, BoxError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// What can go wrong with a [`TypedKV`].
+#[derive(Debug)]
+pub enum TypedError {
+    /// The store itself failed.
+    Io(io::Error),
+    /// A key or value could not be encoded.
+    Encode(BoxError),
+    /// The bytes stored under `key` do not decode to the expected type.
+    Decode { key: ByteString, source: BoxError },
+}
+
+impl fmt::Display for TypedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypedError::Io(err) => write!(f, "{}", err),
+            TypedError::Encode(err) => write!(f, "cannot encode: {}", err),
+            TypedError::Decode { key, source } => write!(
+                f,
+                "cannot decode the record for key {:?}: {}",
+                String::from_utf8_lossy(key),
+                source
+            ),
+        }
+    }
+}
+
+impl Error for TypedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TypedError::Io(err) => Some(err),
+            TypedError::Encode(err) | TypedError::Decode { source: err, .. } => Some(&**err),
+        }
+    }
+}
+
+impl From<io::Error> for TypedError {
+    fn from(err: io::Error) -> Self {
+        TypedError::Io(err)
+    }
+}
+
+pub type Result<T, E = TypedError> = std::result::Result<T, E>;
+
+/// An [`ActionKV`] holding keys of type `K` and values of type `V`, encoded
+/// with `C`.
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use serde::{Deserialize, Serialize};
+/// # use libactionkv::{ActionKV, typed::TypedKV};
+/// #[derive(Serialize, Deserialize)]
+/// struct User {
+///     name: String,
+///     age: u32,
+/// }
+///
+/// let mut store = ActionKV::open(Path::new("users"))?;
+/// store.load()?;
+///
+/// let mut users = TypedKV::<u64, User>::new(store);
+/// users.insert(&1, &User { name: "ada".into(), age: 36 })?;
+/// let ada: Option<User> = users.get(&1)?;
+/// # Ok::<(), libactionkv::typed::TypedError>(())
+/// ```
+#[derive(Debug)]
+pub struct TypedKV<K, V, C = Bincode> {
+    store: ActionKV,
+    codec: C,
+    types: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V> TypedKV<K, V> {
+    /// Wraps a loaded `store`, encoding with [`Bincode`].
+    pub fn new(store: ActionKV) -> Self {
+        TypedKV::with_codec(store, Bincode)
+    }
+}
+
+impl<K, V, C> TypedKV<K, V, C> {
+    /// Wraps a loaded `store`, encoding with `codec`.
+    pub fn with_codec(store: ActionKV, codec: C) -> Self {
+        TypedKV {
+            store,
+            codec,
+            types: PhantomData,
+        }
+    }
+
+    /// The underlying store, for compaction, hints and the like.
+    pub fn store(&mut self) -> &mut ActionKV {
+        &mut self.store
+    }
+
+    pub fn into_inner(self) -> ActionKV {
+        self.store
+    }
+}
+
+impl<K, V, C> TypedKV<K, V, C>
+where
+    K: Serialize,
+    V: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    pub fn get(&mut self, key: &K) -> Result<Option<V>> {
+        let key = self.encode(key)?;
+        match self.store.get(&key)? {
+            Some(value) => self.decode(&key, &value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn insert(&mut self, key: &K, value: &V) -> Result<()> {
+        let (key, value) = (self.encode(key)?, self.encode(value)?);
+        Ok(self.store.insert(&key, &value)?)
+    }
+
+    /// See [`ActionKV::insert_with_ttl`].
+    pub fn insert_with_ttl(&mut self, key: &K, value: &V, ttl: Duration) -> Result<()> {
+        let (key, value) = (self.encode(key)?, self.encode(value)?);
+        Ok(self.store.insert_with_ttl(&key, &value, ttl)?)
+    }
+
+    pub fn delete(&mut self, key: &K) -> Result<()> {
+        let key = self.encode(key)?;
+        Ok(self.store.delete(&key)?)
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<ByteString> {
+        self.codec.encode(value).map_err(TypedError::Encode)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, key: &[u8], bytes: &[u8]) -> Result<T> {
+        self.codec
+            .decode(bytes)
+            .map_err(|source| TypedError::Decode {
+                key: key.to_vec(),
+                source,
+            })
+    }
+}
+
+impl<K, V, C> TypedKV<K, V, C>
+where
+    K: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    /// Every live pair, in the order of the encoded keys.
+    pub fn iter(&self) -> impl Iterator<Item = Result<(K, V)>> + '_ {
+        self.store.scan(..).map(move |kv| {
+            let kv = kv?;
+            let key = self.decode(&kv.key, &kv.key)?;
+            let value = self.decode(&kv.key, &kv.value)?;
+            Ok((key, value))
+        })
+    }
+}
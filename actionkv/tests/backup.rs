@@ -0,0 +1,119 @@
+use std::{fs, io, thread};
+
+use libactionkv::{
+    ActionKV, SharedKV,
+    backup::{self, MANIFEST_FILE},
+};
+
+mod common;
+
+use common::open;
+
+#[test]
+fn test_snapshot_and_restore() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(&dir.path().join("store"))?;
+    store.set_max_segment_size(128);
+    for n in 0..50u32 {
+        store.insert(format!("key{}", n).as_bytes(), &n.to_le_bytes())?;
+    }
+    store.delete(b"key0")?;
+
+    let manifest = store.snapshot(&dir.path().join("snap"))?;
+    assert_eq!(manifest.records, 51);
+    assert_eq!(manifest.keys, 49);
+    assert!(manifest.files.len() > 2);
+
+    // written after the snapshot, so left out of it
+    store.insert(b"late", b"1")?;
+
+    assert_eq!(backup::verify(&dir.path().join("snap"))?, manifest);
+    let restored = dir.path().join("restored");
+    backup::restore(&dir.path().join("snap"), &restored)?;
+    assert!(!restored.join(MANIFEST_FILE).exists());
+
+    let mut restored = open(&restored)?;
+    assert_eq!(restored.stats()?.keys, 49);
+    assert_eq!(restored.get(b"key0")?, None);
+    assert_eq!(restored.get(b"key7")?, Some(7u32.to_le_bytes().to_vec()));
+    assert_eq!(restored.get(b"late")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_rejects_damaged_snapshot() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(&dir.path().join("store"))?;
+    store.insert(b"a", b"1")?;
+    store.snapshot(&dir.path().join("snap"))?;
+
+    let segment = dir.path().join("snap").join("00000000.akv");
+    let mut bytes = fs::read(&segment)?;
+    *bytes.last_mut().unwrap() ^= 0xff;
+    fs::write(&segment, bytes)?;
+
+    let err = backup::restore(&dir.path().join("snap"), &dir.path().join("restored")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(!dir.path().join("restored").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_does_not_write_to_the_snapshot() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(&dir.path().join("store"))?;
+    store.insert(b"a", b"1")?;
+    let snap = dir.path().join("snap");
+    let manifest = store.snapshot(&snap)?;
+
+    // not in the manifest, and holds no records
+    let stray = snap.join("00000009.akv");
+    fs::File::create(&stray)?;
+
+    assert_eq!(backup::verify(&snap)?, manifest);
+    assert_eq!(fs::metadata(&stray)?.len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_refuses_non_empty_destination() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(&dir.path().join("store"))?;
+    fs::write(dir.path().join("taken"), b"")?;
+
+    let err = store.snapshot(dir.path()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_snapshot_while_writing() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = SharedKV::new(open(&dir.path().join("store"))?)?;
+    for n in 0..100u32 {
+        store.insert(&n.to_le_bytes(), b"before")?;
+    }
+
+    let writer = {
+        let store = store.clone();
+        thread::spawn(move || -> io::Result<()> {
+            for n in 100..1000u32 {
+                store.insert(&n.to_le_bytes(), b"during")?;
+            }
+            Ok(())
+        })
+    };
+    let manifest = store.snapshot_to(&dir.path().join("snap"))?;
+    writer.join().unwrap()?;
+
+    backup::verify(&dir.path().join("snap"))?;
+    let snap = open(&dir.path().join("snap"))?;
+    assert_eq!(snap.stats()?.keys, manifest.keys);
+    assert!(manifest.keys >= 100);
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::{fs, io};
+
+use libactionkv::{ActionKV, Recovery, WriteBatch};
+
+mod common;
+
+use common::open;
+
+#[test]
+fn test_batch_is_applied_whole() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(b"a", b"1")?;
+    let mut batch = WriteBatch::new();
+    batch.put(b"b", b"2").delete(b"a").put(b"c", b"3");
+    store.write(batch)?;
+    assert_eq!(store.get(b"a")?, None);
+    assert_eq!(store.get(b"b")?, Some(b"2".to_vec()));
+    drop(store);
+
+    let mut store = open(dir.path())?;
+    let keys: Vec<&[u8]> = store.keys().collect();
+    assert_eq!(keys, [b"b", b"c"]);
+    assert_eq!(store.get(b"c")?, Some(b"3".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn test_load_leaves_an_open_batch_to_its_writer() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("log.akv");
+    fs::File::create(&log)?;
+    let mut writer = open(&log)?;
+    writer.insert(b"a", b"1")?;
+    let committed = fs::metadata(&log)?.len();
+    let mut batch = WriteBatch::new();
+    batch.put(b"b", &[2; 9000]).put(b"c", b"3");
+    writer.write(batch)?;
+    drop(writer);
+
+    // the writer has yet to append the 13 byte commit marker
+    let bytes = fs::read(&log)?;
+    let open_len = bytes.len() - 13;
+    fs::write(&log, &bytes[..open_len])?;
+
+    let mut reader = open(&log)?;
+    assert_eq!(reader.get(b"a")?, Some(b"1".to_vec()));
+    assert_eq!(reader.get(b"b")?, None);
+    assert_eq!(fs::metadata(&log)?.len(), open_len as u64);
+    drop(reader);
+
+    // once the writer finishes, the batch is there for the next load
+    fs::write(&log, &bytes)?;
+    let mut reader = open(&log)?;
+    assert_eq!(reader.get(b"c")?, Some(b"3".to_vec()));
+
+    // a store that writes after finding the batch open cuts it off first
+    fs::write(&log, &bytes[..open_len])?;
+    let mut store = open(&log)?;
+    store.insert(b"d", b"4")?;
+    drop(store);
+    let mut store = open(&log)?;
+    assert_eq!(store.keys().count(), 2);
+    assert_eq!(store.get(b"d")?, Some(b"4".to_vec()));
+
+    // as does loading with tail repair
+    fs::write(&log, &bytes[..open_len])?;
+    let mut store = ActionKV::open(&log)?;
+    store.load_with(Recovery::TruncateTail)?;
+    assert_eq!(fs::metadata(&log)?.len(), committed);
+
+    Ok(())
+}
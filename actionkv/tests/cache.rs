@@ -0,0 +1,79 @@
+use std::io;
+
+use libactionkv::{ActionKV, OpenOptions, WriteBatch};
+
+fn open(path: &std::path::Path, cache_size: usize) -> io::Result<ActionKV> {
+    let mut store = ActionKV::open_with(
+        path,
+        OpenOptions {
+            cache_size,
+            ..OpenOptions::default()
+        },
+    )?;
+    store.load()?;
+    Ok(store)
+}
+
+#[test]
+fn test_repeated_reads_hit_the_cache() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path(), 1 << 20)?;
+    store.insert(b"hot", b"value")?;
+
+    for _ in 0..10 {
+        assert_eq!(store.get(b"hot")?, Some(b"value".to_vec()));
+    }
+    assert_eq!(store.get(b"cold")?, None);
+
+    let stats = store.stats()?;
+    assert_eq!((stats.cache_hits, stats.cache_misses), (9, 1));
+    assert!(stats.cache_bytes > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_writes_invalidate_cached_values() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path(), 1 << 20)?;
+    store.insert(b"a", b"1")?;
+    store.insert(b"b", b"1")?;
+    store.get(b"a")?;
+    store.get(b"b")?;
+
+    store.update(b"a", b"2")?;
+    assert_eq!(store.get(b"a")?, Some(b"2".to_vec()));
+
+    store.delete(b"a")?;
+    assert_eq!(store.get(b"a")?, None);
+
+    let mut batch = WriteBatch::new();
+    batch.put(b"b", b"3");
+    store.write(batch)?;
+    assert_eq!(store.get(b"b")?, Some(b"3".to_vec()));
+
+    store.compact()?;
+    assert_eq!(store.get(b"b")?, Some(b"3".to_vec()));
+    assert_eq!(store.stats()?.cache_hits, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_cache_stays_within_its_size() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path(), 4096)?;
+    for n in 0..100u32 {
+        store.insert(&n.to_le_bytes(), &[0; 200])?;
+        store.get(&n.to_le_bytes())?;
+    }
+    assert!(store.stats()?.cache_bytes <= 4096);
+
+    store.set_cache_size(0);
+    store.get(&0u32.to_le_bytes())?;
+    let stats = store.stats()?;
+    assert_eq!(stats.cache_bytes, 0);
+    assert_eq!(stats.cache_misses, 100);
+
+    Ok(())
+}
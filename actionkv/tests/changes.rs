@@ -0,0 +1,169 @@
+use std::{io, thread, time::Duration};
+
+use libactionkv::{ActionKV, Change, Position, RecordKind, SharedKV, Tail, WriteBatch};
+
+mod common;
+
+use common::open;
+
+#[test]
+fn test_subscribe_sees_changes_under_prefix() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    let changes = store.subscribe(b"user:");
+
+    store.insert(b"user:1", b"ada")?;
+    store.insert(b"other", b"ignored")?;
+    store.update(b"user:1", b"grace")?;
+    let mut batch = WriteBatch::new();
+    batch.put(b"user:2", b"alan").delete(b"user:1");
+    store.write(batch)?;
+
+    let changes: Vec<Change> = changes.try_iter().collect();
+    let summary: Vec<_> = changes
+        .iter()
+        .map(|change| (change.kind, change.key.as_slice(), change.old.is_some()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (RecordKind::Put, &b"user:1"[..], false),
+            (RecordKind::Put, &b"user:1"[..], true),
+            (RecordKind::Put, &b"user:2"[..], false),
+            (RecordKind::Delete, &b"user:1"[..], true),
+        ]
+    );
+    assert_eq!(changes[1].old, Some(changes[0].new));
+    assert_eq!(changes[3].old, Some(changes[1].new));
+
+    Ok(())
+}
+
+#[test]
+fn test_dropped_subscriber_is_forgotten() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = SharedKV::new(open(dir.path())?)?;
+    let changes = store.subscribe(b"");
+    store.insert(b"a", b"1")?;
+    assert_eq!(changes.recv().unwrap().key, b"a");
+
+    drop(changes);
+    store.insert(b"b", b"2")?;
+    store.delete(b"a")?;
+
+    Ok(())
+}
+
+#[test]
+fn test_tail_follows_the_log_across_segments() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.set_max_segment_size(64);
+    let mut tail = store.tail(Position {
+        segment: 0,
+        offset: 0,
+    })?;
+    assert!(tail.try_next()?.is_none());
+
+    for n in 0..10u8 {
+        store.insert(&[n], &[n; 20])?;
+    }
+    let mut batch = WriteBatch::new();
+    batch.put(b"x", b"1").delete(&[0]);
+    store.write(batch)?;
+    assert!(store.segment_ids().count() > 1);
+
+    let mut seen = Vec::new();
+    while let Some((_, record)) = tail.try_next()? {
+        seen.push((record.kind, record.kv.key));
+    }
+    let mut expected: Vec<_> = (0..10u8).map(|n| (RecordKind::Put, vec![n])).collect();
+    expected.push((RecordKind::Put, b"x".to_vec()));
+    expected.push((RecordKind::Delete, vec![0]));
+    assert_eq!(seen, expected);
+
+    // a new tail picks up exactly where the old one stopped
+    let resume = tail.position();
+    store.insert(b"late", b"1")?;
+    let mut tail = store.tail(resume)?;
+    let (_, record) = tail.try_next()?.expect("the late insert");
+    assert_eq!(record.kv.key, b"late");
+    assert!(tail.try_next()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_tail_waits_for_writes() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = SharedKV::new(open(dir.path())?)?;
+    let mut tail = store.tail(Position {
+        segment: 0,
+        offset: 0,
+    })?;
+    assert!(tail.next_timeout(Duration::from_millis(50))?.is_none());
+
+    let writer = {
+        let store = store.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            store.insert(b"a", b"1")
+        })
+    };
+    let (_, record) = tail.next().expect("tails never end")?;
+    assert_eq!(record.kv.key, b"a");
+    writer.join().unwrap()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_tail_does_not_write_to_a_segment_being_created() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(b"a", b"1")?;
+    let mut tail = store.tail(Position {
+        segment: 0,
+        offset: 0,
+    })?;
+
+    // the writer has created the next segment but not written its header
+    let next = dir.path().join("00000001.akv");
+    std::fs::File::create(&next)?;
+
+    let (_, record) = tail.try_next()?.unwrap();
+    assert_eq!(record.kv.key, b"a");
+    assert!(tail.try_next()?.is_none());
+    assert_eq!(tail.position().segment, 1);
+    assert_eq!(std::fs::metadata(&next)?.len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_follow_without_opening_the_store() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(b"before", b"1")?;
+
+    let mut from_end = Tail::follow(dir.path(), None)?;
+    let mut from_start = Tail::follow(
+        dir.path(),
+        Some(Position {
+            segment: 0,
+            offset: 0,
+        }),
+    )?;
+    store.insert(b"after", b"2")?;
+
+    let (_, record) = from_end.try_next()?.unwrap();
+    assert_eq!(record.kv.key, b"after");
+    assert!(from_end.try_next()?.is_none());
+    let (_, record) = from_start.try_next()?.unwrap();
+    assert_eq!(record.kv.key, b"before");
+
+    let missing = Tail::follow(&dir.path().join("missing"), None).unwrap_err();
+    assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+/// Runs `akv STORE ARGS...`, feeding it `stdin`.
+fn akv(store: &Path, args: &[&str], stdin: &[u8]) -> io::Result<Output> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_akv"))
+        .arg(store)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut input) = child.stdin.take() {
+        input.write_all(stdin)?;
+    }
+
+    child.wait_with_output()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).expect("akv prints UTF-8 in these tests")
+}
+
+#[test]
+fn test_subcommands_and_exit_codes() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = dir.path().join("store");
+
+    assert!(
+        akv(&store, &["insert", "user:1", "ada"], b"")?
+            .status
+            .success()
+    );
+    let loaded = akv(&store, &["load"], b"user:2\tgrace\nother\tx\n")?;
+    assert!(loaded.status.success());
+
+    let got = akv(&store, &["get", "user:2"], b"")?;
+    assert_eq!(stdout(&got), "grace\n");
+    let listed = akv(&store, &["list", "user:"], b"")?;
+    assert_eq!(stdout(&listed), "user:1\nuser:2\n");
+    let dumped = akv(&store, &["dump"], b"")?;
+    assert_eq!(stdout(&dumped), "other\tx\nuser:1\tada\nuser:2\tgrace\n");
+
+    // a missing key is exit code 1, and anything else going wrong is 2
+    assert!(akv(&store, &["delete", "user:1"], b"")?.status.success());
+    assert_eq!(akv(&store, &["get", "user:1"], b"")?.status.code(), Some(1));
+    assert_eq!(
+        akv(&store, &["delete", "user:1"], b"")?.status.code(),
+        Some(1)
+    );
+    let bad = akv(&store, &["--encoding", "hex", "get", "zz"], b"")?;
+    assert_eq!(bad.status.code(), Some(2));
+
+    Ok(())
+}
+
+#[test]
+fn test_encodings() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = dir.path().join("store");
+
+    let inserted = akv(&store, &["-e", "hex", "insert", "00ff", "0a0d"], b"")?;
+    assert!(inserted.status.success());
+
+    let hex = akv(&store, &["-e", "hex", "dump"], b"")?;
+    assert_eq!(stdout(&hex), "00ff\t0a0d\n");
+    let base64 = akv(&store, &["-e", "base64", "get", "AP8="], b"")?;
+    assert_eq!(stdout(&base64), "Cg0=\n");
+    let raw = akv(&store, &["-e", "raw", "get", "AP8="], b"")?;
+    assert_eq!(raw.status.code(), Some(1));
+    let raw = akv(&store, &["-e", "raw", "list"], b"")?;
+    assert_eq!(raw.stdout, b"\x00\xff\n");
+
+    // bytes that are not UTF-8 cannot be printed as text
+    let utf8 = akv(&store, &["dump"], b"")?;
+    assert_eq!(utf8.status.code(), Some(2));
+
+    Ok(())
+}
+
+#[test]
+fn test_reading_a_missing_store_does_not_create_it() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = dir.path().join("nope");
+
+    for args in [&["fsck"][..], &["get", "a"], &["stats"], &["dump"]] {
+        let output = akv(&store, args, b"")?;
+        assert_eq!(output.status.code(), Some(2), "akv {:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("no such store"));
+        assert!(!store.exists(), "akv {:?} created the store", args);
+    }
+
+    // nor is an empty directory taken for an empty store
+    std::fs::create_dir(&store)?;
+    assert_eq!(akv(&store, &["stats"], b"")?.status.code(), Some(2));
+    assert_eq!(std::fs::read_dir(&store)?.count(), 0);
+
+    Ok(())
+}
@@ -0,0 +1,12 @@
+//! Helpers shared by the integration tests.
+
+use std::{io, path::Path};
+
+use libactionkv::ActionKV;
+
+/// Opens the store at `path` and loads its index.
+pub fn open(path: &Path) -> io::Result<ActionKV> {
+    let mut store = ActionKV::open(path)?;
+    store.load()?;
+    Ok(store)
+}
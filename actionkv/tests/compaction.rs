@@ -0,0 +1,56 @@
+use std::{fs, io};
+
+use libactionkv::ActionKV;
+
+mod common;
+
+use common::open;
+
+#[test]
+fn test_compact_keeps_only_live_records() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    for n in 0..100u32 {
+        store.update(b"counter", &n.to_le_bytes())?;
+    }
+    store.insert(b"gone", b"soon")?;
+    store.delete(b"gone")?;
+    store.insert(b"kept", b"yes")?;
+    let segment = dir.path().join("00000000.akv");
+    let before = fs::metadata(&segment)?.len();
+
+    store.compact()?;
+    assert!(fs::metadata(&segment)?.len() < before / 10);
+    assert_eq!(store.get(b"counter")?, Some(99u32.to_le_bytes().to_vec()));
+    assert_eq!(store.get(b"gone")?, None);
+
+    // the new index holds after a reopen, and the log takes new writes
+    store.insert(b"after", b"compact")?;
+    drop(store);
+    let mut store = open(dir.path())?;
+    let keys: Vec<&[u8]> = store.keys().collect();
+    assert_eq!(keys, [&b"after"[..], b"counter", b"kept"]);
+    assert_eq!(store.get(b"kept")?, Some(b"yes".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn test_compact_needs_a_loaded_store() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(b"a", b"1")?;
+    drop(store);
+
+    let mut unloaded = ActionKV::open(dir.path())?;
+    let err = unloaded.compact().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = unloaded.compact_segment(0).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    drop(unloaded);
+
+    let mut store = open(dir.path())?;
+    assert_eq!(store.get(b"a")?, Some(b"1".to_vec()));
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+#![cfg(any(feature = "lz4", feature = "zstd"))]
+
+use std::{fs, io, path::Path};
+
+use libactionkv::{ActionKV, Compression};
+
+mod common;
+
+use common::open;
+
+fn blob() -> Vec<u8> {
+    let items: Vec<String> = (0..200)
+        .map(|i| format!("{{\"id\":{},\"name\":\"item\",\"tags\":[\"a\",\"b\"]}}", i))
+        .collect();
+    format!("[{}]", items.join(",")).into_bytes()
+}
+
+fn store_len(path: &Path) -> io::Result<u64> {
+    let mut store = open(path)?;
+    Ok(store.stats()?.bytes)
+}
+
+fn round_trip(compression: Compression) -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store");
+    let value = blob();
+
+    let mut store = ActionKV::open(&path)?;
+    store.set_compression(compression);
+    store.load()?;
+    store.insert(b"blob", &value)?;
+    store.insert(b"small", b"{}")?;
+    drop(store);
+
+    let compressed_len = store_len(&path)?;
+    assert!(compressed_len < value.len() as u64 / 2);
+
+    // reading needs no setting: the codec is recorded per record
+    let mut store = open(&path)?;
+    assert_eq!(store.get(b"blob")?, Some(value.clone()));
+    assert_eq!(store.get(b"small")?, Some(b"{}".to_vec()));
+    assert!(store.fsck()?.is_empty());
+
+    // compaction rewrites with the store's own setting
+    store.compact()?;
+    assert!(store.stats()?.bytes > value.len() as u64);
+    assert_eq!(store.get(b"blob")?, Some(value));
+
+    Ok(())
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn test_lz4_round_trip() -> io::Result<()> {
+    round_trip(Compression::Lz4)
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd_round_trip() -> io::Result<()> {
+    round_trip(Compression::Zstd)
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn test_checksum_covers_compressed_bytes() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.akv");
+    fs::File::create(&path)?;
+
+    let mut store = ActionKV::open(&path)?;
+    store.set_compression(Compression::Lz4);
+    store.load()?;
+    store.insert(b"blob", &blob())?;
+    drop(store);
+
+    let mut bytes = fs::read(&path)?;
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0x01;
+    fs::write(&path, &bytes)?;
+
+    let mut store = ActionKV::open(&path)?;
+    assert_eq!(store.fsck()?.len(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::{io, path::Path, time::Duration};
+
+use libactionkv::{ActionKV, KeyValuePair, OpenOptions, SharedKV, SyncPolicy, WriteBatch};
+
+fn open_with(path: &Path, sync: SyncPolicy) -> io::Result<ActionKV> {
+    let mut store = ActionKV::open_with(
+        path,
+        OpenOptions {
+            sync,
+            max_segment_size: 256,
+            ..OpenOptions::default()
+        },
+    )?;
+    store.load()?;
+    Ok(store)
+}
+
+#[test]
+fn test_every_policy_keeps_writes_across_reopen() -> io::Result<()> {
+    let policies = [
+        SyncPolicy::Always,
+        SyncPolicy::EveryN(3),
+        SyncPolicy::Interval(Duration::from_secs(3600)),
+        SyncPolicy::Never,
+    ];
+
+    for sync in policies {
+        let dir = tempfile::tempdir()?;
+        {
+            let mut store = open_with(dir.path(), sync)?;
+            for n in 0..20u32 {
+                store.insert(format!("key{:02}", n).as_bytes(), &n.to_le_bytes())?;
+            }
+            let mut batch = WriteBatch::new();
+            batch.delete(b"key00");
+            store.write(batch)?;
+            store.insert_all((20..25u32).map(|n| {
+                Ok(KeyValuePair {
+                    key: format!("key{:02}", n).into_bytes(),
+                    value: n.to_le_bytes().to_vec(),
+                })
+            }))?;
+            store.flush()?;
+            store.sync()?;
+        }
+
+        let mut store = open_with(dir.path(), SyncPolicy::Never)?;
+        assert!(store.segment_ids().count() > 1, "{:?}", sync);
+        assert_eq!(store.get(b"key00")?, None, "{:?}", sync);
+        for n in 1..25u32 {
+            let key = format!("key{:02}", n);
+            assert_eq!(
+                store.get(key.as_bytes())?,
+                Some(n.to_le_bytes().to_vec()),
+                "{:?}",
+                sync
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_open_with_applies_options() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open_with(dir.path(), SyncPolicy::EveryN(0))?;
+    store.insert(b"a", &[0; 300])?;
+    store.insert(b"b", b"1")?;
+
+    // the first segment was over the limit, so the second write rolled over
+    assert_eq!(store.segment_ids().collect::<Vec<_>>(), [0, 1]);
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_sync() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = SharedKV::new(open_with(dir.path(), SyncPolicy::Never)?)?;
+    store.insert(b"a", b"1")?;
+    store.flush()?;
+    store.sync()?;
+    drop(store);
+
+    let mut store = open_with(dir.path(), SyncPolicy::Never)?;
+    assert_eq!(store.get(b"a")?, Some(b"1".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn test_flushed_writes_are_visible_before_a_sync() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open_with(dir.path(), SyncPolicy::Never)?;
+    store.insert(b"a", b"1")?;
+    store.flush()?;
+
+    // a second handle reads what the first has handed to the OS
+    let mut other = open_with(dir.path(), SyncPolicy::Never)?;
+    assert_eq!(other.get(b"a")?, Some(b"1".to_vec()));
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::io;
+
+use libactionkv::{
+    ActionKV,
+    export::{self, Format},
+};
+
+mod common;
+
+use common::open;
+
+fn round_trip(format: Format) -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    let mut source = open(&dir.path().join("source"))?;
+    source.insert(b"greeting", b"hello, \"world\"\n")?;
+    source.insert(b"\x00\xff", b"binary")?;
+    source.insert(b"gone", b"soon")?;
+    source.delete(b"gone")?;
+
+    let mut exported = Vec::new();
+    assert_eq!(export::export(&source, format, &mut exported)?, 2);
+
+    let mut dest = ActionKV::open(&dir.path().join("dest"))?;
+    dest.set_max_segment_size(32);
+    dest.load()?;
+    assert_eq!(export::import(&mut dest, format, exported.as_slice())?, 2);
+    drop(dest);
+
+    let mut dest = open(&dir.path().join("dest"))?;
+    assert_eq!(dest.keys().count(), 2);
+    assert_eq!(dest.get(b"greeting")?, Some(b"hello, \"world\"\n".to_vec()));
+    assert_eq!(dest.get(b"\x00\xff")?, Some(b"binary".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn test_jsonl_round_trip() -> io::Result<()> {
+    round_trip(Format::Jsonl)
+}
+
+#[test]
+fn test_csv_round_trip() -> io::Result<()> {
+    round_trip(Format::Csv)
+}
+
+#[test]
+fn test_import_keeps_rows_before_a_bad_one() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(&dir.path().join("store"))?;
+
+    let input = b"{\"key\":\"a\",\"value\":\"1\"}\n{\"key\":\"b\",\"value\":\"!\",\"encoding\":\"base64\"}\n";
+    let err = export::import(&mut store, Format::Jsonl, &input[..]).err();
+
+    assert_eq!(err.map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+    assert_eq!(store.get(b"a")?, Some(b"1".to_vec()));
+    assert_eq!(store.keys().count(), 1);
+
+    Ok(())
+}
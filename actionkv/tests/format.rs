@@ -0,0 +1,100 @@
+use std::{fs, io, path::Path};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use crc::{CRC_32_CKSUM, Crc};
+use libactionkv::{ActionKV, FORMAT_VERSION};
+
+mod common;
+
+use common::open;
+
+const CKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_CKSUM);
+
+/// Writes `records` as an unversioned log, where an empty value deletes.
+fn write_v0(path: &Path, records: &[(&[u8], &[u8])]) -> io::Result<()> {
+    let mut log = Vec::new();
+    for (key, value) in records {
+        let data = [*key, *value].concat();
+        log.write_u32::<LittleEndian>(CKSUM.checksum(&data))?;
+        log.write_u32::<LittleEndian>(key.len() as u32)?;
+        log.write_u32::<LittleEndian>(value.len() as u32)?;
+        log.extend_from_slice(&data);
+    }
+
+    fs::write(path, log)
+}
+
+#[test]
+fn test_delete_is_not_an_empty_value() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(b"empty", b"")?;
+    store.insert(b"deleted", b"1")?;
+    store.delete(b"deleted")?;
+    assert_eq!(store.get(b"empty")?, Some(Vec::new()));
+    assert_eq!(store.get(b"deleted")?, None);
+    drop(store);
+
+    let mut store = open(dir.path())?;
+    assert_eq!(store.get(b"empty")?, Some(Vec::new()));
+    assert_eq!(store.get(b"deleted")?, None);
+    assert_eq!(store.keys().count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_unversioned_log_is_read_and_upgraded() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("old.akv");
+    write_v0(
+        &log,
+        &[(b"a", b"1"), (b"b", b"2"), (b"a", b"3"), (b"b", b"")],
+    )?;
+
+    let mut store = open(&log)?;
+    assert_eq!(store.version(), 0);
+    assert_eq!(store.get(b"a")?, Some(b"3".to_vec()));
+    assert_eq!(store.get(b"b")?, None);
+
+    // the first write rewrites the log in the current format
+    store.insert(b"c", b"")?;
+    assert_eq!(store.version(), FORMAT_VERSION);
+    drop(store);
+
+    let mut store = open(&log)?;
+    assert_eq!(store.version(), FORMAT_VERSION);
+    assert_eq!(store.get(b"a")?, Some(b"3".to_vec()));
+    assert_eq!(store.get(b"b")?, None);
+    assert_eq!(store.get(b"c")?, Some(Vec::new()));
+
+    Ok(())
+}
+
+#[test]
+fn test_upgrade_drops_the_legacy_index_key() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("old.akv");
+    // as left behind by the old akv_disk, which kept its index in the log
+    write_v0(&log, &[(b"a", b"1"), (b"+index", b"\x01\x00\x00\x00")])?;
+
+    let mut store = open(&log)?;
+    assert_eq!(store.get(b"+index")?, None);
+    store.compact()?;
+    drop(store);
+
+    let mut store = open(&log)?;
+    assert_eq!(store.version(), FORMAT_VERSION);
+    let keys: Vec<&[u8]> = store.keys().collect();
+    assert_eq!(keys, [b"a"]);
+    assert_eq!(store.find(b"+index")?, None);
+
+    // in the current format it is an ordinary key
+    store.insert(b"+index", b"mine")?;
+    store.compact()?;
+    drop(store);
+    let mut store = open(&log)?;
+    assert_eq!(store.get(b"+index")?, Some(b"mine".to_vec()));
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::{fs, io, path::Path};
+
+use libactionkv::ActionKV;
+
+mod common;
+
+use common::open;
+
+#[test]
+fn test_hint_is_used_and_the_tail_replayed() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(b"a", b"1")?;
+    store.insert(b"b", b"2")?;
+    store.save_hint()?;
+    assert!(dir.path().join("index.hint").is_file());
+
+    // written after the hint, so only found by replaying the tail
+    store.delete(b"a")?;
+    store.insert(b"c", b"3")?;
+    drop(store);
+
+    let mut store = open(dir.path())?;
+    let keys: Vec<&[u8]> = store.keys().collect();
+    assert_eq!(keys, [b"b", b"c"]);
+    assert_eq!(store.get(b"b")?, Some(b"2".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn test_damaged_hint_is_ignored() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(b"a", b"1")?;
+    store.save_hint()?;
+    drop(store);
+
+    let hint = dir.path().join("index.hint");
+    let mut bytes = fs::read(&hint)?;
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0xff;
+    fs::write(&hint, bytes)?;
+
+    let mut store = open(dir.path())?;
+    assert_eq!(store.get(b"a")?, Some(b"1".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn test_save_hint_needs_a_loaded_store() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(b"a", b"1")?;
+    drop(store);
+
+    // an empty index saved as a hint would hide every record
+    let mut unloaded = ActionKV::open(dir.path())?;
+    let err = unloaded.save_hint().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let mut store = open(dir.path())?;
+    assert_eq!(store.get(b"a")?, Some(b"1".to_vec()));
+
+    Ok(())
+}
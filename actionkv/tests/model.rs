@@ -0,0 +1,124 @@
+//! Random sequences of writes and reopens, checked against a `BTreeMap`.
+//!
+//! Each case is driven by a seeded RNG; a failure names the seed and the
+//! step so it can be replayed.
+
+use std::{collections::BTreeMap, io, path::Path};
+
+use libactionkv::{ActionKV, OpenOptions, SyncPolicy, WriteBatch};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+const CASES: u64 = 32;
+const STEPS: usize = 300;
+
+type Model = BTreeMap<Vec<u8>, Vec<u8>>;
+
+fn open(path: &Path) -> io::Result<ActionKV> {
+    // durability is not what is being tested, and syncing is slow
+    let options = OpenOptions {
+        sync: SyncPolicy::Never,
+        max_segment_size: 512,
+        ..OpenOptions::default()
+    };
+    let mut store = ActionKV::open_with(path, options)?;
+    store.load()?;
+    Ok(store)
+}
+
+/// A key from a small pool, so that keys are often overwritten and deleted.
+fn key(rng: &mut StdRng) -> Vec<u8> {
+    format!("key{}", rng.random_range(0..20)).into_bytes()
+}
+
+fn value(rng: &mut StdRng) -> Vec<u8> {
+    let len = rng.random_range(0..100);
+    (0..len).map(|_| rng.random()).collect()
+}
+
+fn check(store: &mut ActionKV, model: &Model, seed: u64, step: usize) -> io::Result<()> {
+    for n in 0..20 {
+        let key = format!("key{}", n).into_bytes();
+        assert_eq!(
+            store.get(&key)?.as_ref(),
+            model.get(&key),
+            "seed {} step {}: {:?}",
+            seed,
+            step,
+            String::from_utf8_lossy(&key)
+        );
+    }
+
+    let scanned = store
+        .scan(..)
+        .map(|kv| kv.map(|kv| (kv.key, kv.value)))
+        .collect::<io::Result<Model>>()?;
+    assert_eq!(&scanned, model, "seed {} step {}: scan", seed, step);
+
+    Ok(())
+}
+
+#[test]
+fn test_random_operations_match_model() -> io::Result<()> {
+    for seed in 0..CASES {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let dir = tempfile::tempdir()?;
+        let mut store = open(dir.path())?;
+        let mut model = Model::new();
+
+        for step in 0..STEPS {
+            match rng.random_range(0..100) {
+                0..40 => {
+                    let (key, value) = (key(&mut rng), value(&mut rng));
+                    store.insert(&key, &value)?;
+                    model.insert(key, value);
+                }
+                40..55 => {
+                    let (key, value) = (key(&mut rng), value(&mut rng));
+                    store.update(&key, &value)?;
+                    model.insert(key, value);
+                }
+                55..75 => {
+                    let key = key(&mut rng);
+                    store.delete(&key)?;
+                    model.remove(&key);
+                }
+                75..85 => {
+                    let mut batch = WriteBatch::new();
+                    for _ in 0..rng.random_range(1..5) {
+                        let key = key(&mut rng);
+                        if rng.random_bool(0.7) {
+                            let value = value(&mut rng);
+                            batch.put(&key, &value);
+                            model.insert(key, value);
+                        } else {
+                            batch.delete(&key);
+                            model.remove(&key);
+                        }
+                    }
+                    store.write(batch)?;
+                }
+                85..93 => {
+                    if rng.random_bool(0.5) {
+                        store.save_hint()?;
+                    }
+                    drop(store);
+                    store = open(dir.path())?;
+                }
+                93..97 => store.compact()?,
+                _ => {
+                    let id = rng.random_range(0..store.segment_ids().count());
+                    let id = store.segment_ids().nth(id).expect("id is in range");
+                    store.compact_segment(id)?;
+                }
+            }
+
+            check(&mut store, &model, seed, step)?;
+        }
+
+        drop(store);
+        let mut store = open(dir.path())?;
+        check(&mut store, &model, seed, STEPS)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::{fs, io, path::Path};
+
+use libactionkv::{ActionKV, Corruption, Recovery};
+
+mod common;
+
+use common::open;
+
+/// Offsets of the second and third of the 15 byte records written by
+/// `write_log`, and the end of the log.
+const B: u64 = 23;
+const C: u64 = 38;
+const END: u64 = 53;
+
+fn write_log(path: &Path) -> io::Result<()> {
+    fs::File::create(path)?;
+    let mut store = open(path)?;
+    store.insert(b"a", b"1")?;
+    store.insert(b"b", b"2")?;
+    store.insert(b"c", b"3")?;
+    assert_eq!(fs::metadata(path)?.len(), END);
+
+    Ok(())
+}
+
+#[test]
+fn test_bad_checksum_is_a_typed_error() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("log.akv");
+    write_log(&log)?;
+    let mut bytes = fs::read(&log)?;
+    bytes[C as usize - 1] ^= 0x01;
+    fs::write(&log, bytes)?;
+
+    let mut store = ActionKV::open(&log)?;
+    let err = store.load().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let corruption = Corruption::from_io(&err).unwrap();
+    assert_eq!((corruption.segment, corruption.offset), (0, B));
+    assert_eq!((corruption.key_len, corruption.val_len), (1, 1));
+    assert!(!corruption.is_torn());
+
+    // a good record follows, so this is not a torn tail
+    let mut store = ActionKV::open(&log)?;
+    assert!(store.load_with(Recovery::TruncateTail).is_err());
+    assert_eq!(fs::metadata(&log)?.len(), END);
+
+    let bad = store.fsck()?;
+    assert_eq!(bad.len(), 1);
+    assert_eq!(bad[0].offset, B);
+
+    Ok(())
+}
+
+#[test]
+fn test_torn_tail_is_truncated_on_request() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("log.akv");
+    write_log(&log)?;
+    let bytes = fs::read(&log)?;
+    fs::write(&log, &bytes[..END as usize - 1])?;
+
+    let mut store = ActionKV::open(&log)?;
+    let err = store.load().unwrap_err();
+    let corruption = Corruption::from_io(&err).unwrap();
+    assert_eq!(corruption.offset, C);
+    assert!(corruption.is_torn());
+    assert_eq!(fs::metadata(&log)?.len(), END - 1);
+
+    let mut store = ActionKV::open(&log)?;
+    store.load_with(Recovery::TruncateTail)?;
+    assert_eq!(fs::metadata(&log)?.len(), C);
+    assert_eq!(store.get(b"a")?, Some(b"1".to_vec()));
+    assert_eq!(store.get(b"b")?, Some(b"2".to_vec()));
+    assert_eq!(store.get(b"c")?, None);
+    assert!(store.fsck()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_damaged_length_in_the_middle_is_not_a_torn_tail() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("log.akv");
+    fs::File::create(&log)?;
+    let mut store = open(&log)?;
+    for n in 0..10 {
+        store.insert(format!("k{}", n).as_bytes(), b"value")?;
+    }
+    drop(store);
+
+    // the top byte of k3's value length, which now runs past the end
+    let k3 = 8 + 3 * 20;
+    let mut bytes = fs::read(&log)?;
+    bytes[k3 + 12] |= 0x80;
+    fs::write(&log, &bytes)?;
+
+    let mut store = ActionKV::open(&log)?;
+    let err = store.load().unwrap_err();
+    let corruption = Corruption::from_io(&err).unwrap();
+    assert_eq!(corruption.offset, k3 as u64);
+    assert!(corruption.is_torn());
+
+    let mut store = ActionKV::open(&log)?;
+    let err = store.load_with(Recovery::TruncateTail).unwrap_err();
+    assert_eq!(Corruption::from_io(&err).unwrap().offset, k3 as u64);
+    assert_eq!(fs::read(&log)?, bytes);
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::{
+    io,
+    net::TcpListener,
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use libactionkv::{
+    SharedKV, WriteBatch,
+    replication::{Follower, Leader},
+};
+use redis::{Commands, Connection, RedisResult};
+
+/// Polls `check` until it holds, failing the test after a few seconds.
+fn eventually<F: FnMut() -> bool>(mut check: F) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !check() {
+        assert!(Instant::now() < deadline, "replica never caught up");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_follower_copies_leader() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let leader_store = SharedKV::open(&dir.path().join("leader"))?;
+    leader_store.insert(b"before", b"1")?;
+
+    let leader = Leader::bind("127.0.0.1:0", leader_store.clone())?;
+    let addr = leader.local_addr()?;
+    thread::spawn(move || leader.run());
+
+    let follower_store = SharedKV::open(&dir.path().join("follower"))?;
+    let mut follower = Follower::new(follower_store.clone())?;
+    thread::spawn(move || follower.run(addr));
+
+    leader_store.insert(b"after", b"2")?;
+    leader_store.insert_with_ttl(b"ttl", b"3", Duration::from_secs(3600))?;
+    let mut batch = WriteBatch::new();
+    batch.put(b"x", b"4").put(b"y", b"5").delete(b"before");
+    leader_store.write(batch)?;
+    leader_store.delete(b"after")?;
+    leader_store.insert(b"last", b"6")?;
+
+    eventually(|| follower_store.get(b"last").unwrap().is_some());
+    for key in [&b"before"[..], b"after", b"ttl", b"x", b"y", b"last"] {
+        assert_eq!(
+            follower_store.get(key)?,
+            leader_store.get(key)?,
+            "{:?}",
+            key
+        );
+    }
+
+    Ok(())
+}
+
+/// An `akv_server` process that is killed when dropped.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Server {
+    fn start(store: &Path, extra: &[String]) -> io::Result<Self> {
+        let port = free_port()?;
+        let child = Command::new(env!("CARGO_BIN_EXE_akv_server"))
+            .arg(store)
+            .arg("--listen")
+            .arg(format!("127.0.0.1:{}", port))
+            .args(extra)
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        Ok(Server { child, port })
+    }
+
+    fn connect(&self) -> RedisResult<Connection> {
+        let client = redis::Client::open(format!("redis://127.0.0.1:{}/", self.port))?;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            match client.get_connection() {
+                Ok(con) => return Ok(con),
+                Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn free_port() -> io::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+#[test]
+fn test_two_processes() -> RedisResult<()> {
+    let dir = tempfile::tempdir()?;
+    let replication = format!("127.0.0.1:{}", free_port()?);
+
+    let leader = Server::start(
+        &dir.path().join("leader"),
+        &["--replicate".into(), replication.clone()],
+    )?;
+    let mut leader_con = leader.connect()?;
+    let () = leader_con.set("greeting", "hello")?;
+
+    let follow = ["--follow".to_string(), replication];
+    let follower = Server::start(&dir.path().join("follower"), &follow)?;
+    let mut follower_con = follower.connect()?;
+    eventually(|| {
+        follower_con
+            .get::<_, Option<String>>("greeting")
+            .unwrap()
+            .is_some()
+    });
+
+    let err = follower_con.set::<_, _, ()>("greeting", "bye").unwrap_err();
+    assert_eq!(err.code(), Some("READONLY"));
+
+    // a restarted follower carries on from where it stopped
+    drop(follower_con);
+    drop(follower);
+    let () = leader_con.set("greeting", "hi again")?;
+    let () = leader_con.del("missing")?;
+    let () = leader_con.set("other", "1")?;
+
+    let follower = Server::start(&dir.path().join("follower"), &follow)?;
+    let mut follower_con = follower.connect()?;
+    eventually(|| {
+        follower_con
+            .get::<_, Option<String>>("other")
+            .unwrap()
+            .is_some()
+    });
+    let greeting: String = follower_con.get("greeting")?;
+    assert_eq!(greeting, "hi again");
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::{io, ops::Bound};
+
+use libactionkv::KeyValuePair;
+
+mod common;
+
+use common::open;
+
+fn keys<I>(kvs: I) -> io::Result<Vec<Vec<u8>>>
+where
+    I: Iterator<Item = io::Result<KeyValuePair>>,
+{
+    kvs.map(|kv| kv.map(|kv| kv.key)).collect()
+}
+
+#[test]
+fn test_keys_and_scans_are_in_key_order() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    for key in ["user:3", "user:1", "admin", "user:2", "users", "\u{ff}"] {
+        store.insert(key.as_bytes(), key.as_bytes())?;
+    }
+    store.delete(b"user:2")?;
+
+    let listed: Vec<&[u8]> = store.keys().collect();
+    assert_eq!(
+        listed,
+        [
+            &b"admin"[..],
+            b"user:1",
+            b"user:3",
+            b"users",
+            "\u{ff}".as_bytes()
+        ]
+    );
+
+    let start: &[u8] = b"user:1";
+    let end: &[u8] = b"users";
+    let scanned = keys(store.scan((Bound::Included(start), Bound::Excluded(end))))?;
+    assert_eq!(scanned, [b"user:1".to_vec(), b"user:3".to_vec()]);
+
+    let prefixed = keys(store.scan_prefix(b"user"))?;
+    assert_eq!(
+        prefixed,
+        [b"user:1".to_vec(), b"user:3".to_vec(), b"users".to_vec()]
+    );
+    assert_eq!(keys(store.scan_prefix(b"\xc3"))?, ["\u{ff}".as_bytes()]);
+    assert_eq!(keys(store.scan_prefix(b""))?.len(), 5);
+
+    let kv = store.scan_prefix(b"admin").next().unwrap()?;
+    assert_eq!(kv.value, b"admin");
+
+    Ok(())
+}
+
+#[test]
+fn test_prefix_of_max_bytes_is_unbounded() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(b"\xfe", b"1")?;
+    store.insert(b"\xff", b"2")?;
+    store.insert(b"\xff\xff\x00", b"3")?;
+
+    assert_eq!(
+        keys(store.scan_prefix(b"\xff"))?,
+        [b"\xff".to_vec(), b"\xff\xff\x00".to_vec()]
+    );
+    assert_eq!(
+        keys(store.scan_prefix(b"\xff\xff"))?,
+        [b"\xff\xff\x00".to_vec()]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::{fs, io, path::Path};
+
+use libactionkv::{ActionKV, Position};
+
+fn open(path: &Path) -> io::Result<ActionKV> {
+    let mut store = ActionKV::open(path)?;
+    store.set_max_segment_size(256);
+    store.load()?;
+    Ok(store)
+}
+
+#[test]
+fn test_log_rolls_over_into_numbered_segments() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    for n in 0..40u32 {
+        store.insert(format!("key{:02}", n).as_bytes(), &[n as u8; 20])?;
+    }
+
+    let ids: Vec<u32> = store.segment_ids().collect();
+    assert!(ids.len() > 3);
+    assert_eq!(ids, (0..ids.len() as u32).collect::<Vec<_>>());
+    for id in &ids[..ids.len() - 1] {
+        let len = fs::metadata(dir.path().join(format!("{:08}.akv", id)))?.len();
+        assert!(
+            (256..256 + 64).contains(&len),
+            "segment {} is {} bytes",
+            id,
+            len
+        );
+    }
+    assert_eq!(
+        store.index[&b"key00"[..]],
+        Position {
+            segment: 0,
+            offset: 8
+        }
+    );
+    assert_eq!(store.index[&b"key39"[..]].segment, *ids.last().unwrap());
+    drop(store);
+
+    let mut store = open(dir.path())?;
+    assert_eq!(store.segment_ids().count(), ids.len());
+    for n in 0..40u32 {
+        let value = store.get(format!("key{:02}", n).as_bytes())?;
+        assert_eq!(value, Some(vec![n as u8; 20]));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compacting_a_segment_leaves_the_others_alone() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    for n in 0..10u32 {
+        store.insert(format!("old{}", n).as_bytes(), &[0; 20])?;
+    }
+    for n in 0..10u32 {
+        store.delete(format!("old{}", n).as_bytes())?;
+    }
+    store.insert(b"new", b"1")?;
+    let last = store.segment_ids().last().unwrap();
+    let last_path = dir.path().join(format!("{:08}.akv", last));
+    let last_bytes = fs::read(&last_path)?;
+
+    // segment 0 only held values deleted since, so it goes away
+    store.compact_segment(0)?;
+    assert!(!dir.path().join("00000000.akv").exists());
+    assert_eq!(store.segment_ids().next(), Some(1));
+    assert_eq!(fs::read(&last_path)?, last_bytes);
+    drop(store);
+
+    let mut store = open(dir.path())?;
+    assert_eq!(store.keys().collect::<Vec<_>>(), [b"new"]);
+    assert_eq!(store.get(b"old0")?, None);
+
+    Ok(())
+}
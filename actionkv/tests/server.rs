@@ -0,0 +1,107 @@
+use std::{thread, time::Duration};
+
+use libactionkv::{SharedKV, server::Server};
+use redis::{Commands, Connection, RedisResult};
+
+fn start(dir: &tempfile::TempDir) -> RedisResult<Connection> {
+    let store = SharedKV::open(&dir.path().join("store"))?;
+    let server = Server::bind("127.0.0.1:0", store)?;
+    let addr = server.local_addr()?;
+    thread::spawn(move || server.run());
+
+    redis::Client::open(format!("redis://{}/", addr))?.get_connection()
+}
+
+#[test]
+fn test_get_set_del_exists() -> RedisResult<()> {
+    let dir = tempfile::tempdir()?;
+    let mut con = start(&dir)?;
+
+    let missing: Option<Vec<u8>> = con.get("greeting")?;
+    assert_eq!(missing, None);
+
+    let () = con.set("greeting", "hello")?;
+    let () = con.set("binary", &b"\x00\r\n\xff"[..])?;
+    let greeting: String = con.get("greeting")?;
+    let binary: Vec<u8> = con.get("binary")?;
+    assert_eq!(greeting, "hello");
+    assert_eq!(binary, b"\x00\r\n\xff");
+
+    let found: i64 = con.exists(&["greeting", "binary", "nope"])?;
+    assert_eq!(found, 2);
+
+    let deleted: i64 = con.del(&["greeting", "nope"])?;
+    assert_eq!(deleted, 1);
+    let exists: bool = con.exists("greeting")?;
+    assert!(!exists);
+
+    let err = con.get::<_, ()>(&["a", "b"][..]).err();
+    assert!(err.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_scan() -> RedisResult<()> {
+    let dir = tempfile::tempdir()?;
+    let mut con = start(&dir)?;
+
+    for i in 0..25 {
+        let () = con.set(format!("user:{:02}", i), i)?;
+        let () = con.set(format!("order:{:02}", i), i)?;
+    }
+
+    let mut users: Vec<String> = con.scan_match("user:*")?.collect();
+    users.sort();
+    let expected: Vec<String> = (0..25).map(|i| format!("user:{:02}", i)).collect();
+    assert_eq!(users, expected);
+
+    let all: Vec<String> = con.scan()?.collect();
+    assert_eq!(all.len(), 50);
+
+    // expired keys are left out, like GET leaves them out
+    let () = redis::cmd("SET")
+        .arg("user:99")
+        .arg("gone")
+        .arg("PX")
+        .arg(1)
+        .query(&mut con)?;
+    thread::sleep(Duration::from_millis(10));
+    let users: Vec<String> = con.scan_match("user:*")?.collect();
+    assert_eq!(users.len(), 25);
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_resumes_after_the_last_key() -> RedisResult<()> {
+    let dir = tempfile::tempdir()?;
+    let mut con = start(&dir)?;
+
+    for key in ["b", "d", "f"] {
+        let () = con.set(key, 1)?;
+    }
+
+    let (cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+        .arg(0)
+        .arg("COUNT")
+        .arg(2)
+        .query(&mut con)?;
+    assert_eq!(keys, ["b", "d"]);
+
+    // keys written before the cursor do not shift where it carries on
+    let () = con.set("a", 1)?;
+    let () = con.set("e", 1)?;
+    let _: i64 = con.del("f")?;
+    let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+        .arg(cursor)
+        .arg("COUNT")
+        .arg(2)
+        .query(&mut con)?;
+    assert_eq!((next, keys), (0, vec!["e".to_string()]));
+
+    let err = redis::cmd("SCAN").arg(12345).query::<()>(&mut con).err();
+    assert!(err.is_some());
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::{
+    io,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+use libactionkv::{ActionKV, SharedKV, WriteBatch};
+
+const BATCHES: u64 = 400;
+const READERS: usize = 4;
+
+fn open(path: &Path) -> io::Result<SharedKV> {
+    let mut store = ActionKV::open(path)?;
+    store.set_max_segment_size(1024);
+    store.load()?;
+    SharedKV::new(store)
+}
+
+fn counter(value: Option<Vec<u8>>) -> u64 {
+    let value = value.expect("the counters are never deleted");
+    u64::from_le_bytes(value.try_into().expect("a counter is eight bytes"))
+}
+
+#[test]
+fn test_readers_see_whole_batches_through_compaction() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = open(dir.path())?;
+    let mut batch = WriteBatch::new();
+    batch
+        .put(b"x", &0u64.to_le_bytes())
+        .put(b"y", &0u64.to_le_bytes());
+    store.write(batch)?;
+    let done = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        let readers: Vec<_> = (0..READERS)
+            .map(|_| {
+                scope.spawn(|| -> io::Result<()> {
+                    let mut last = 0;
+                    loop {
+                        let finished = done.load(Ordering::Acquire);
+                        // both counters move in one batch, so a view that
+                        // holds one must hold the other at the same value
+                        let snapshot = store.snapshot();
+                        let x = counter(snapshot.get(b"x")?);
+                        let y = counter(snapshot.get(b"y")?);
+                        assert_eq!(x, y);
+                        assert!(x >= last, "went back from {} to {}", last, x);
+                        last = x;
+
+                        // a plain read must find its record even if the
+                        // segment it was in has since been compacted
+                        assert!(counter(store.get(b"x")?) >= last);
+                        if finished {
+                            return Ok(());
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let writer = scope.spawn(|| -> io::Result<()> {
+            for n in 1..=BATCHES {
+                let mut batch = WriteBatch::new();
+                batch
+                    .put(b"x", &n.to_le_bytes())
+                    .put(format!("filler{}", n % 7).as_bytes(), &[0; 64])
+                    .put(b"y", &n.to_le_bytes());
+                store.write(batch)?;
+                if n % 50 == 0 {
+                    store.compact()?;
+                }
+            }
+            Ok(())
+        });
+
+        let written = writer.join().expect("the writer does not panic");
+        done.store(true, Ordering::Release);
+        for reader in readers {
+            reader.join().expect("no reader saw a torn batch")?;
+        }
+        written
+    })?;
+
+    assert_eq!(counter(store.get(b"x")?), BATCHES);
+    drop(store);
+    let store = open(dir.path())?;
+    assert_eq!(counter(store.get(b"y")?), BATCHES);
+    assert_eq!(store.snapshot().len(), 9);
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use libactionkv::{ActionKV, Clock, SharedKV};
+
+/// A clock that only moves when told to.
+#[derive(Debug, Clone, Default)]
+struct ManualClock {
+    millis: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    fn advance(&self, by: Duration) {
+        self.millis
+            .fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.millis.load(Ordering::SeqCst))
+    }
+}
+
+fn open(path: &std::path::Path, clock: &ManualClock) -> io::Result<ActionKV> {
+    let mut store = ActionKV::open(path)?;
+    store.set_clock(clock.clone());
+    store.load()?;
+    Ok(store)
+}
+
+#[test]
+fn test_expired_keys_read_as_missing() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let clock = ManualClock::default();
+    let mut store = open(&dir.path().join("store"), &clock)?;
+
+    store.insert(b"forever", b"1")?;
+    store.insert_with_ttl(b"session", b"2", Duration::from_secs(30))?;
+    assert_eq!(store.get(b"session")?, Some(b"2".to_vec()));
+
+    clock.advance(Duration::from_secs(29));
+    assert_eq!(store.get(b"session")?, Some(b"2".to_vec()));
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(store.get(b"session")?, None);
+    assert_eq!(store.get(b"forever")?, Some(b"1".to_vec()));
+
+    let live: Vec<_> = store
+        .scan(..)
+        .map(|kv| kv.map(|kv| kv.key))
+        .collect::<io::Result<_>>()?;
+    assert_eq!(live, vec![b"forever".to_vec()]);
+
+    Ok(())
+}
+
+#[test]
+fn test_load_and_compact_drop_expired_keys() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store");
+    let clock = ManualClock::default();
+
+    let mut store = open(&path, &clock)?;
+    store.insert(b"a", b"old")?;
+    store.insert_with_ttl(b"a", b"new", Duration::from_secs(1))?;
+    store.insert_with_ttl(b"b", b"short", Duration::from_secs(1))?;
+    store.insert_with_ttl(b"c", b"long", Duration::from_secs(60))?;
+    drop(store);
+
+    clock.advance(Duration::from_secs(2));
+    let mut store = open(&path, &clock)?;
+    // an expired write must not bring back the value it replaced
+    assert_eq!(store.keys().collect::<Vec<_>>(), vec![b"c"]);
+
+    store.compact()?;
+    clock.advance(Duration::from_secs(60));
+    store.compact()?;
+    assert_eq!(store.keys().count(), 0);
+
+    let store = open(&path, &ManualClock::default())?;
+    assert_eq!(store.keys().count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_ttl() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let clock = ManualClock::default();
+    let store = SharedKV::new(open(&dir.path().join("store"), &clock)?)?;
+
+    store.insert_with_ttl(b"token", b"abc", Duration::from_millis(500))?;
+    let before = store.snapshot();
+
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(store.get(b"token")?, None);
+    assert_eq!(before.get(b"token")?, Some(b"abc".to_vec()));
+    assert_eq!(store.snapshot().scan(..).count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_listings_leave_out_expired_keys() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let clock = ManualClock::default();
+    let mut store = open(&dir.path().join("store"), &clock)?;
+    store.insert(b"a", b"1")?;
+    store.insert_with_ttl(b"b", b"2", Duration::from_secs(1))?;
+    store.insert_with_ttl(b"c", b"3", Duration::from_secs(60))?;
+    assert_eq!(store.keys().count(), 3);
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(store.keys().collect::<Vec<_>>(), [b"a", b"c"]);
+    assert_eq!(store.stats()?.keys, 2);
+
+    let store = SharedKV::new(store)?;
+    assert!(store.contains_key(b"a"));
+    assert!(!store.contains_key(b"b"));
+    assert!(store.contains_key(b"c"));
+    let snapshot = store.snapshot();
+    assert_eq!(snapshot.keys().collect::<Vec<_>>(), [b"a", b"c"]);
+    assert_eq!(snapshot.len(), 2);
+
+    clock.advance(Duration::from_secs(60));
+    assert!(!store.contains_key(b"c"));
+    assert_eq!(store.snapshot().keys().collect::<Vec<_>>(), [b"a"]);
+    // a snapshot judges expiry as of when it was taken
+    assert_eq!(snapshot.len(), 2);
+
+    Ok(())
+}
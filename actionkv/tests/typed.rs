@@ -0,0 +1,74 @@
+use std::io;
+
+use libactionkv::{
+    ActionKV, TypedKV,
+    typed::{Json, TypedError},
+};
+use serde::{Deserialize, Serialize};
+
+mod common;
+
+use common::open;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct User {
+    name: String,
+    age: u32,
+    tags: Vec<String>,
+}
+
+fn user(name: &str, age: u32) -> User {
+    User {
+        name: name.to_string(),
+        age,
+        tags: vec!["admin".to_string()],
+    }
+}
+
+#[test]
+fn test_bincode_round_trip() -> Result<(), TypedError> {
+    let dir = tempfile::tempdir()?;
+    let mut users = TypedKV::<u64, User>::new(open(dir.path())?);
+    users.insert(&1, &user("ada", 36))?;
+    users.insert(&2, &user("grace", 45))?;
+    users.delete(&2)?;
+
+    assert_eq!(users.get(&1)?, Some(user("ada", 36)));
+    assert_eq!(users.get(&2)?, None);
+    drop(users);
+
+    let mut users = TypedKV::<u64, User>::new(open(dir.path())?);
+    assert_eq!(users.get(&1)?, Some(user("ada", 36)));
+    let all = users.iter().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(all, [(1, user("ada", 36))]);
+
+    Ok(())
+}
+
+#[test]
+fn test_json_is_stored_as_text() -> Result<(), TypedError> {
+    let dir = tempfile::tempdir()?;
+    let mut users = TypedKV::<String, User, Json>::with_codec(open(dir.path())?, Json);
+    users.insert(&"ada".to_string(), &user("ada", 36))?;
+
+    let mut store = users.into_inner();
+    let raw = store.get(br#""ada""#)?.expect("stored under the JSON key");
+    assert!(raw.starts_with(br#"{"name":"ada""#));
+
+    Ok(())
+}
+
+#[test]
+fn test_wrong_type_is_a_decode_error() -> Result<(), TypedError> {
+    let dir = tempfile::tempdir()?;
+    let mut store = open(dir.path())?;
+    store.insert(br#""ada""#, b"not json")?;
+
+    let mut users = TypedKV::<String, User, Json>::with_codec(store, Json);
+    match users.get(&"ada".to_string()) {
+        Err(TypedError::Decode { key, .. }) => assert_eq!(key, br#""ada""#),
+        other => panic!("expected a decode error, got {:?}", other),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! Command-line argument parsing for chip8-emu.
+//!
+//! This module defines the command-line interface using `clap`.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// Command-line arguments for chip8-emu
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct Args {
+    /// The `.ch8` ROM to run
+    pub rom: PathBuf,
+
+    /// Clock speed, in instructions per second
+    #[arg(long, default_value_t = 700, value_parser = clap::value_parser!(u32).range(1..))]
+    pub hz: u32,
+
+    /// Stop after executing this many instructions
+    #[arg(short = 'n', long)]
+    pub steps: Option<u64>,
+
+    /// Run without the terminal display, printing the screen and registers
+    /// once the program stops
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Start in the interactive debugger instead of running the ROM
+    #[arg(long, conflicts_with_all = ["headless", "steps"])]
+    pub debug: bool,
+}
@@ -0,0 +1,81 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::cpu::{CPU, CpuFault, StepOutcome};
+
+// The timers count down at 60 Hz, and the CPU runs in frames of that length
+pub const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Why the CPU stopped running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    // The program halted
+    Halted,
+    // The step limit was reached
+    StepLimit,
+    // An instruction could not be executed
+    Fault(CpuFault),
+}
+
+// Runs a CPU at a fixed clock speed in 60 Hz frames, ticking its timers
+// once per frame however many instructions that is
+#[derive(Debug)]
+pub struct Clock {
+    hz: u64,
+    steps: Option<u64>,
+    executed: u64,
+    frames: u32,
+    start: Instant,
+}
+
+impl Clock {
+    pub fn new(hz: u32, steps: Option<u64>) -> Self {
+        Self {
+            hz: hz as u64,
+            steps,
+            executed: 0,
+            frames: 0,
+            start: Instant::now(),
+        }
+    }
+
+    // Instructions executed so far
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    // Execute as many instructions as the clock speed allows by the end of
+    // the next frame, then tick the timers
+    pub fn frame(&mut self, cpu: &mut CPU) -> Option<Stop> {
+        self.frames += 1;
+
+        let due = self.hz * self.frames as u64 / 60;
+        while self.executed < due {
+            if self.steps.is_some_and(|limit| self.executed >= limit) {
+                return Some(Stop::StepLimit);
+            }
+
+            match cpu.step() {
+                Ok(StepOutcome::Continue) => self.executed += 1,
+                Ok(StepOutcome::Halted) => {
+                    self.executed += 1;
+                    return Some(Stop::Halted);
+                }
+                Err(fault) => return Some(Stop::Fault(fault)),
+            }
+        }
+
+        cpu.tick_timers();
+        None
+    }
+
+    // Sleep until the next frame is due
+    pub fn wait(&self) {
+        let next = self.start + FRAME * self.frames;
+        if let Some(wait) = next.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+}
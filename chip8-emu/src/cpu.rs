@@ -1,12 +1,129 @@
+use std::{error::Error, fmt, io, ops::Range};
+
+// Display size in pixels
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+
+// Monochrome framebuffer, indexed by row then column
+pub type Display = [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+
+// Where the built-in hex font sprites live in memory, 5 bytes per digit
+pub const FONT_START: usize = 0x050;
+
+// Where ROMs are loaded and execution starts
+pub const PROGRAM_START: usize = 0x200;
+
+// Largest ROM that fits between PROGRAM_START and the end of memory
+pub const MAX_ROM_SIZE: usize = 0x1000 - PROGRAM_START;
+
+// Sprites for the hex digits 0-F, 4 pixels wide and 5 rows high
+#[rustfmt::skip]
+pub const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// What executing an instruction led to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    // The program carries on
+    Continue,
+    // The program halted, on 0x0000 or on a jump to itself that would spin
+    // forever. The PC is left on that instruction
+    Halted,
+}
+
+// An instruction the CPU cannot execute. The PC is left on the faulting
+// instruction, so the host can fix things up and step again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuFault {
+    // CALL with all 16 stack levels in use
+    StackOverflow { pc: u16, opcode: u16 },
+    // RET with an empty stack
+    StackUnderflow { pc: u16, opcode: u16 },
+    // The PC points past the last whole instruction in memory
+    PcOutOfBounds { pc: u16 },
+    // No instruction has this opcode
+    UnknownOpcode { pc: u16, opcode: u16 },
+    // The instruction reads or writes memory from I past the end of memory
+    MemoryOutOfRange { pc: u16, opcode: u16, i: u16 },
+}
+
+impl fmt::Display for CpuFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CpuFault::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow at {:#05x} ({:04X})", pc, opcode)
+            }
+            CpuFault::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow at {:#05x} ({:04X})", pc, opcode)
+            }
+            CpuFault::PcOutOfBounds { pc } => {
+                write!(f, "program counter out of bounds at {:#05x}", pc)
+            }
+            CpuFault::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode at {:#05x} ({:04X})", pc, opcode)
+            }
+            CpuFault::MemoryOutOfRange { pc, opcode, i } => write!(
+                f,
+                "memory out of range at {:#05x} ({:04X}) with I = {:#05x}",
+                pc, opcode, i
+            ),
+        }
+    }
+}
+
+impl Error for CpuFault {}
+
+// A fault before the PC and opcode are added
+#[derive(Debug)]
+enum Fault {
+    StackOverflow,
+    StackUnderflow,
+    UnknownOpcode,
+    MemoryOutOfRange { i: u16 },
+}
+
+impl Fault {
+    fn at(self, pc: u16, opcode: u16) -> CpuFault {
+        match self {
+            Fault::StackOverflow => CpuFault::StackOverflow { pc, opcode },
+            Fault::StackUnderflow => CpuFault::StackUnderflow { pc, opcode },
+            Fault::UnknownOpcode => CpuFault::UnknownOpcode { pc, opcode },
+            Fault::MemoryOutOfRange { i } => CpuFault::MemoryOutOfRange { pc, opcode, i },
+        }
+    }
+}
+
 // CHIP-8 CPU with 16 registers, 4KB memory, and a call stack
 #[derive(Debug)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     pub registers: [u8; 16],
     pub memory: [u8; 0x1000],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub display: Display,
+    pub keypad: [bool; 16],
     position_in_memory: usize,
     stack: [u16; 16],
     stack_pointer: usize,
+    rng_state: u32,
 }
 
 impl Default for CPU {
@@ -20,58 +137,199 @@ impl CPU {
         Self {
             registers: [0; 16],
             memory: [0; 0x1000],
+            i: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            display: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            keypad: [false; 16],
             position_in_memory: 0,
             stack: [0; 16],
             stack_pointer: 0,
+            rng_state: 0x2545_F491,
         }
     }
 
-    // Main execution loop
-    pub fn run(&mut self) {
+    // Seed the random number generator used by RND (zero is replaced, as
+    // xorshift would only ever produce zeros from it)
+    pub fn seed(&mut self, seed: u32) {
+        self.rng_state = if seed == 0 { 0x2545_F491 } else { seed };
+    }
+
+    // Address of the next instruction
+    pub fn pc(&self) -> u16 {
+        self.position_in_memory as u16
+    }
+
+    // Move execution to `pc`
+    pub fn set_pc(&mut self, pc: u16) {
+        self.position_in_memory = pc as usize;
+    }
+
+    // Return addresses of the subroutines being run, outermost first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer]
+    }
+
+    // Number of stack levels in use
+    pub fn stack_pointer(&self) -> usize {
+        self.stack_pointer
+    }
+
+    // Count both timers down by one; the host calls this at 60 Hz
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // Copy the font to FONT_START and `rom` to PROGRAM_START, and start
+    // executing from there
+    pub fn load_rom(&mut self, rom: &[u8]) -> io::Result<()> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "ROM is {} bytes, at most {} fit in memory",
+                    rom.len(),
+                    MAX_ROM_SIZE
+                ),
+            ));
+        }
+
+        self.memory[FONT_START..FONT_START + FONT.len()].copy_from_slice(&FONT);
+        self.memory[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        self.position_in_memory = PROGRAM_START;
+        Ok(())
+    }
+
+    // Main execution loop, until the program halts or faults
+    pub fn run(&mut self) -> Result<StepOutcome, CpuFault> {
         loop {
-            let opcode = self.read_opcode();
-            self.position_in_memory += 2;
+            match self.step()? {
+                StepOutcome::Continue => {}
+                outcome => return Ok(outcome),
+            }
+        }
+    }
+
+    // Execute a single instruction
+    pub fn step(&mut self) -> Result<StepOutcome, CpuFault> {
+        let pc = self.position_in_memory;
+        let opcode = self
+            .read_opcode()
+            .ok_or(CpuFault::PcOutOfBounds { pc: pc as u16 })?;
+        self.position_in_memory += 2;
+
+        match self.execute(opcode) {
+            Ok(StepOutcome::Halted) => {
+                // stay on the halting instruction
+                self.position_in_memory = pc;
+                Ok(StepOutcome::Halted)
+            }
+            Ok(outcome) => Ok(outcome),
+            Err(fault) => {
+                // leave the PC on the faulting instruction
+                self.position_in_memory = pc;
+                Err(fault.at(pc as u16, opcode))
+            }
+        }
+    }
+
+    // Decode and execute `opcode`, with the PC already past it
+    fn execute(&mut self, opcode: u16) -> Result<StepOutcome, Fault> {
+        // Extract opcode parts
+        let vx = ((opcode & 0x0F00) >> 8) as u8; // register x
+        let vy = ((opcode & 0x00F0) >> 4) as u8; // register y
+        let addr = opcode & 0x0FFF; // 12-bit address
+        let kk = (opcode & 0x00FF) as u8; // 8-bit value
+        let op_minor = (opcode & 0x000F) as u8; // last nibble
 
-            // Extract opcode parts
-            let vx = ((opcode & 0x0F00) >> 8) as u8; // register x
-            let vy = ((opcode & 0x00F0) >> 4) as u8; // register y
-            let addr = opcode & 0x0FFF; // 12-bit address
-            let kk = (opcode & 0x00FF) as u8; // 8-bit value
-            let op_minor = (opcode & 0x000F) as u8; // last nibble
-
-            match opcode {
-                0x0000 => return,
-                0x00E0 => { /* Clear Screen */ }
-                0x00EE => self.ret(),                  // Return from subroutine
-                0x1000..=0x1FFF => self.jmp(addr),     // Jump to address
-                0x2000..=0x2FFF => self.call(addr),    // Call subroutine
-                0x3000..=0x3FFF => self.se(vx, kk),    // Skip if Vx == kk
-                0x4000..=0x4FFF => self.sne(vx, kk),   // Skip if Vx != kk
-                0x5000..=0x5FFF => self.se_xy(vx, vy), // Skip if Vx == Vy
-                0x6000..=0x6FFF => self.ld(vx, kk),    // Vx = kk
-                0x7000..=0x7FFF => self.add(vx, kk),   // Vx += kk
-                0x8000..=0x8FFF => match op_minor {
-                    0x00 => self.ld_xy(vx, vy),  // Vx = Vy
-                    0x01 => self.or_xy(vx, vy),  // Vx |= Vy
-                    0x02 => self.and_xy(vx, vy), // Vx &= Vy
-                    0x03 => self.xor_xy(vx, vy), // Vx ^= Vy
-                    0x04 => self.add_xy(vx, vy), // Vx += Vy (with carry)
-                    _ => {
-                        todo!("opcode: {:04x}", opcode);
-                    }
-                },
-                0x9000..=0x9FFF => self.sne_xy(vx, vy), // Skip if Vx != Vy
-                _ => todo!("opcode {:04x}", opcode),
+        match opcode {
+            0x0000..=0x0FFF => match opcode {
+                0x0000 => return Ok(StepOutcome::Halted),
+                0x00E0 => self.cls(),  // Clear screen
+                0x00EE => self.ret()?, // Return from subroutine
+                _ => {}                // SYS addr, ignored
+            },
+            0x1000..=0x1FFF => {
+                // Jump to address; a jump to itself can never be left
+                let spinning = addr as usize + 2 == self.position_in_memory;
+                self.jmp(addr);
+                if spinning {
+                    return Ok(StepOutcome::Halted);
+                }
             }
+            0x2000..=0x2FFF => self.call(addr)?, // Call subroutine
+            0x3000..=0x3FFF => self.se(vx, kk),  // Skip if Vx == kk
+            0x4000..=0x4FFF => self.sne(vx, kk), // Skip if Vx != kk
+            0x5000..=0x5FFF => match op_minor {
+                0x00 => self.se_xy(vx, vy), // Skip if Vx == Vy
+                _ => return Err(Fault::UnknownOpcode),
+            },
+            0x6000..=0x6FFF => self.ld(vx, kk),  // Vx = kk
+            0x7000..=0x7FFF => self.add(vx, kk), // Vx += kk
+            0x8000..=0x8FFF => match op_minor {
+                0x00 => self.ld_xy(vx, vy),   // Vx = Vy
+                0x01 => self.or_xy(vx, vy),   // Vx |= Vy
+                0x02 => self.and_xy(vx, vy),  // Vx &= Vy
+                0x03 => self.xor_xy(vx, vy),  // Vx ^= Vy
+                0x04 => self.add_xy(vx, vy),  // Vx += Vy (with carry)
+                0x05 => self.sub_xy(vx, vy),  // Vx -= Vy (with borrow)
+                0x06 => self.shr(vx),         // Vx >>= 1
+                0x07 => self.subn_xy(vx, vy), // Vx = Vy - Vx (with borrow)
+                0x0E => self.shl(vx),         // Vx <<= 1
+                _ => return Err(Fault::UnknownOpcode),
+            },
+            0x9000..=0x9FFF => match op_minor {
+                0x00 => self.sne_xy(vx, vy), // Skip if Vx != Vy
+                _ => return Err(Fault::UnknownOpcode),
+            },
+            0xA000..=0xAFFF => self.ld_i(addr),   // I = addr
+            0xB000..=0xBFFF => self.jmp_v0(addr), // Jump to V0 + addr
+            0xC000..=0xCFFF => self.rnd(vx, kk),  // Vx = random & kk
+            0xD000..=0xDFFF => self.drw(vx, vy, op_minor)?, // Draw sprite
+            0xE000..=0xEFFF => match kk {
+                0x9E => self.skp(vx),  // Skip if key Vx is down
+                0xA1 => self.sknp(vx), // Skip if key Vx is up
+                _ => return Err(Fault::UnknownOpcode),
+            },
+            0xF000..=0xFFFF => match kk {
+                0x07 => self.ld_vx_dt(vx),    // Vx = delay timer
+                0x0A => self.ld_key(vx),      // Wait for a key, Vx = key
+                0x15 => self.ld_dt(vx),       // Delay timer = Vx
+                0x18 => self.ld_st(vx),       // Sound timer = Vx
+                0x1E => self.add_i(vx),       // I += Vx
+                0x29 => self.ld_font(vx),     // I = sprite for digit Vx
+                0x33 => self.ld_bcd(vx)?,     // Store BCD of Vx at I
+                0x55 => self.store_regs(vx)?, // Store V0..=Vx at I
+                0x65 => self.load_regs(vx)?,  // Load V0..=Vx from I
+                _ => return Err(Fault::UnknownOpcode),
+            },
         }
+
+        Ok(StepOutcome::Continue)
     }
 
-    // Read 16-bit opcode from memory
-    fn read_opcode(&self) -> u16 {
+    // Read 16-bit opcode from memory, if the PC is not past the end of it
+    fn read_opcode(&self) -> Option<u16> {
         let p = self.position_in_memory;
-        let op_byte1 = self.memory[p] as u16;
-        let op_byte2 = self.memory[p + 1] as u16;
-        op_byte1 << 8 | op_byte2
+        let op_byte1 = *self.memory.get(p)? as u16;
+        let op_byte2 = *self.memory.get(p + 1)? as u16;
+        Some(op_byte1 << 8 | op_byte2)
+    }
+
+    // The `len` memory addresses starting at I
+    fn at_i(&self, len: usize) -> Result<Range<usize>, Fault> {
+        let start = self.i as usize;
+        if start + len > self.memory.len() {
+            return Err(Fault::MemoryOutOfRange { i: self.i });
+        }
+
+        Ok(start..start + len)
+    }
+
+    // Clear screen
+    fn cls(&mut self) {
+        self.display = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
     }
 
     // Jump to address
@@ -79,32 +337,39 @@ impl CPU {
         self.position_in_memory = addr as usize;
     }
 
+    // Jump to address plus V0
+    fn jmp_v0(&mut self, addr: u16) {
+        self.position_in_memory = (addr + self.registers[0] as u16) as usize;
+    }
+
     // Call subroutine
-    fn call(&mut self, addr: u16) {
+    fn call(&mut self, addr: u16) -> Result<(), Fault> {
         let sp = &mut self.stack_pointer;
         let stack = &mut self.stack;
 
-        if *sp > (*stack).len() {
-            panic!("stack overflow")
+        if *sp >= (*stack).len() {
+            return Err(Fault::StackOverflow);
         }
 
         (*stack)[*sp] = self.position_in_memory as u16;
         *sp += 1;
 
         self.position_in_memory = addr as usize;
+        Ok(())
     }
 
     // Return from subroutine
-    fn ret(&mut self) {
+    fn ret(&mut self) -> Result<(), Fault> {
         let sp = &mut self.stack_pointer;
 
         if *sp == 0 {
-            panic!("stack underflow")
+            return Err(Fault::StackUnderflow);
         }
 
         *sp -= 1;
         let call_addr = self.stack[*sp];
         self.position_in_memory = call_addr as usize;
+        Ok(())
     }
 
     // Skip if Vx == kk
@@ -116,7 +381,7 @@ impl CPU {
 
     // Skip if Vx == Vy
     fn se_xy(&mut self, vx: u8, vy: u8) {
-        if self.registers[vx as usize] != self.registers[vy as usize] {
+        if self.registers[vx as usize] == self.registers[vy as usize] {
             self.position_in_memory += 2;
         }
     }
@@ -135,6 +400,20 @@ impl CPU {
         }
     }
 
+    // Skip if the key in Vx is down
+    fn skp(&mut self, vx: u8) {
+        if self.keypad[(self.registers[vx as usize] & 0x0F) as usize] {
+            self.position_in_memory += 2;
+        }
+    }
+
+    // Skip if the key in Vx is up
+    fn sknp(&mut self, vx: u8) {
+        if !self.keypad[(self.registers[vx as usize] & 0x0F) as usize] {
+            self.position_in_memory += 2;
+        }
+    }
+
     // Load immediate: Vx = kk
     fn ld(&mut self, vx: u8, kk: u8) {
         self.registers[vx as usize] = kk;
@@ -145,9 +424,27 @@ impl CPU {
         self.registers[vx as usize] = self.registers[vy as usize];
     }
 
-    // Add immediate: Vx += kk
+    // Load index: I = addr
+    fn ld_i(&mut self, addr: u16) {
+        self.i = addr;
+    }
+
+    // Random byte masked with kk: Vx = rand & kk
+    fn rnd(&mut self, vx: u8, kk: u8) {
+        // xorshift32
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_state = x;
+
+        self.registers[vx as usize] = (x >> 24) as u8 & kk;
+    }
+
+    // Add immediate: Vx += kk, no carry flag
     fn add(&mut self, vx: u8, kk: u8) {
-        self.registers[vx as usize] += kk;
+        let x_ = &mut self.registers[vx as usize];
+        *x_ = x_.wrapping_add(kk);
     }
 
     // Add registers: Vx += Vy, VF = carry
@@ -160,6 +457,42 @@ impl CPU {
         self.registers[0xF] = if overflow { 1 } else { 0 };
     }
 
+    // Subtract registers: Vx -= Vy, VF = NOT borrow
+    fn sub_xy(&mut self, vx: u8, vy: u8) {
+        let arg1 = self.registers[vx as usize];
+        let arg2 = self.registers[vy as usize];
+        let (val, borrow) = arg1.overflowing_sub(arg2);
+        self.registers[vx as usize] = val;
+
+        self.registers[0xF] = if borrow { 0 } else { 1 };
+    }
+
+    // Reverse subtract: Vx = Vy - Vx, VF = NOT borrow
+    fn subn_xy(&mut self, vx: u8, vy: u8) {
+        let arg1 = self.registers[vx as usize];
+        let arg2 = self.registers[vy as usize];
+        let (val, borrow) = arg2.overflowing_sub(arg1);
+        self.registers[vx as usize] = val;
+
+        self.registers[0xF] = if borrow { 0 } else { 1 };
+    }
+
+    // Shift right: Vx >>= 1, VF = bit shifted out
+    fn shr(&mut self, vx: u8) {
+        let x_ = self.registers[vx as usize];
+        self.registers[vx as usize] = x_ >> 1;
+
+        self.registers[0xF] = x_ & 0x01;
+    }
+
+    // Shift left: Vx <<= 1, VF = bit shifted out
+    fn shl(&mut self, vx: u8) {
+        let x_ = self.registers[vx as usize];
+        self.registers[vx as usize] = x_ << 1;
+
+        self.registers[0xF] = x_ >> 7;
+    }
+
     // Bitwise AND: Vx &= Vy
     fn and_xy(&mut self, vx: u8, vy: u8) {
         let x_ = &self.registers[vx as usize];
@@ -183,4 +516,340 @@ impl CPU {
 
         self.registers[vx as usize] = *x_ ^ *y_;
     }
+
+    // Draw an n-byte sprite from I at (Vx, Vy), VF = collision. The start
+    // position wraps around the screen, the sprite itself is clipped
+    fn drw(&mut self, vx: u8, vy: u8, n: u8) -> Result<(), Fault> {
+        let sprite = self.at_i(n as usize)?;
+        let x0 = self.registers[vx as usize] as usize % DISPLAY_WIDTH;
+        let y0 = self.registers[vy as usize] as usize % DISPLAY_HEIGHT;
+        let mut collision = false;
+
+        for row in 0..n as usize {
+            let y = y0 + row;
+            if y >= DISPLAY_HEIGHT {
+                break;
+            }
+
+            let sprite = self.memory[sprite.start + row];
+            for col in 0..8 {
+                let x = x0 + col;
+                if x >= DISPLAY_WIDTH {
+                    break;
+                }
+
+                if sprite & (0x80 >> col) != 0 {
+                    let pixel = &mut self.display[y][x];
+                    collision |= *pixel;
+                    *pixel = !*pixel;
+                }
+            }
+        }
+
+        self.registers[0xF] = if collision { 1 } else { 0 };
+        Ok(())
+    }
+
+    // Wait for a key press: Vx = key. Runs this instruction again until a
+    // key is down
+    fn ld_key(&mut self, vx: u8) {
+        match self.keypad.iter().position(|&down| down) {
+            Some(key) => self.registers[vx as usize] = key as u8,
+            None => self.position_in_memory -= 2,
+        }
+    }
+
+    // Read delay timer: Vx = DT
+    fn ld_vx_dt(&mut self, vx: u8) {
+        self.registers[vx as usize] = self.delay_timer;
+    }
+
+    // Set delay timer: DT = Vx
+    fn ld_dt(&mut self, vx: u8) {
+        self.delay_timer = self.registers[vx as usize];
+    }
+
+    // Set sound timer: ST = Vx
+    fn ld_st(&mut self, vx: u8) {
+        self.sound_timer = self.registers[vx as usize];
+    }
+
+    // Add to index: I += Vx
+    fn add_i(&mut self, vx: u8) {
+        self.i = self.i.wrapping_add(self.registers[vx as usize] as u16);
+    }
+
+    // Point I at the font sprite for the low digit of Vx
+    fn ld_font(&mut self, vx: u8) {
+        let digit = (self.registers[vx as usize] & 0x0F) as u16;
+        self.i = FONT_START as u16 + digit * 5;
+    }
+
+    // Store hundreds, tens and ones of Vx at I, I+1 and I+2
+    fn ld_bcd(&mut self, vx: u8) -> Result<(), Fault> {
+        let at = self.at_i(3)?;
+        let x_ = self.registers[vx as usize];
+        self.memory[at].copy_from_slice(&[x_ / 100, x_ / 10 % 10, x_ % 10]);
+        Ok(())
+    }
+
+    // Store V0..=Vx in memory starting at I, leaving I unchanged
+    fn store_regs(&mut self, vx: u8) -> Result<(), Fault> {
+        let at = self.at_i(vx as usize + 1)?;
+        self.memory[at].copy_from_slice(&self.registers[..=vx as usize]);
+        Ok(())
+    }
+
+    // Load V0..=Vx from memory starting at I, leaving I unchanged
+    fn load_regs(&mut self, vx: u8) -> Result<(), Fault> {
+        let at = self.at_i(vx as usize + 1)?;
+        self.registers[..=vx as usize].copy_from_slice(&self.memory[at]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CPU, CpuFault, FONT_START, MAX_ROM_SIZE, PROGRAM_START, StepOutcome};
+
+    // Load `program` at 0x000 and run it from there until it halts
+    fn run(cpu: &mut CPU, program: &[u8]) {
+        load(cpu, program);
+        assert_eq!(cpu.run(), Ok(StepOutcome::Halted));
+    }
+
+    // Load `program` at 0x000 and point the PC at it
+    fn load(cpu: &mut CPU, program: &[u8]) {
+        cpu.memory[..program.len()].copy_from_slice(program);
+        cpu.jmp(0x000);
+    }
+
+    #[test]
+    fn test_add_wraps() {
+        let mut cpu = CPU::new();
+        run(&mut cpu, &[0x60, 0xFF, 0x70, 0x02]);
+
+        assert_eq!(cpu.registers[0], 0x01);
+        assert_eq!(cpu.registers[0xF], 0);
+    }
+
+    #[test]
+    fn test_se_xy() {
+        let mut cpu = CPU::new();
+        #[rustfmt::skip]
+        run(&mut cpu, &[
+            0x60, 0x05, // LD V0, 5
+            0x61, 0x05, // LD V1, 5
+            0x50, 0x10, // SE V0, V1
+            0x62, 0x01, // LD V2, 1 (skipped)
+        ]);
+
+        assert_eq!(cpu.registers[2], 0);
+    }
+
+    #[test]
+    fn test_sub_flags() {
+        let mut cpu = CPU::new();
+        #[rustfmt::skip]
+        run(&mut cpu, &[
+            0x60, 0x05, // LD V0, 5
+            0x61, 0x07, // LD V1, 7
+            0x80, 0x15, // SUB V0, V1
+            0x82, 0xF0, // LD V2, VF
+            0x63, 0x03, // LD V3, 3
+            0x83, 0x17, // SUBN V3, V1
+        ]);
+
+        assert_eq!(cpu.registers[0], 0xFE);
+        assert_eq!(cpu.registers[2], 0);
+        assert_eq!(cpu.registers[3], 4);
+        assert_eq!(cpu.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_shifts() {
+        let mut cpu = CPU::new();
+        #[rustfmt::skip]
+        run(&mut cpu, &[
+            0x60, 0x81, // LD V0, 0x81
+            0x61, 0x81, // LD V1, 0x81
+            0x80, 0x06, // SHR V0
+            0x82, 0xF0, // LD V2, VF
+            0x81, 0x0E, // SHL V1
+        ]);
+
+        assert_eq!(cpu.registers[0], 0x40);
+        assert_eq!(cpu.registers[2], 1);
+        assert_eq!(cpu.registers[1], 0x02);
+        assert_eq!(cpu.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_bcd_and_register_dump() {
+        let mut cpu = CPU::new();
+        #[rustfmt::skip]
+        run(&mut cpu, &[
+            0x60, 0xFE, // LD V0, 254
+            0xA3, 0x00, // LD I, 0x300
+            0xF0, 0x33, // LD B, V0
+            0xF2, 0x65, // LD V2, [I]
+        ]);
+
+        assert_eq!(cpu.memory[0x300..0x303], [2, 5, 4]);
+        assert_eq!(cpu.registers[..3], [2, 5, 4]);
+        assert_eq!(cpu.i, 0x300);
+    }
+
+    #[test]
+    fn test_draw_collision_and_clear() {
+        let mut cpu = CPU::new();
+        cpu.memory[0x300] = 0b1100_0000;
+        #[rustfmt::skip]
+        let program = [
+            0x60, 0x3F, // LD V0, 63
+            0xA3, 0x00, // LD I, 0x300
+            0xD0, 0x11, // DRW V0, V1, 1
+        ];
+        run(&mut cpu, &program);
+
+        // clipped at the right edge
+        assert!(cpu.display[0][63]);
+        assert!(!cpu.display[0][0]);
+        assert_eq!(cpu.registers[0xF], 0);
+
+        // drawing the same sprite again erases it
+        run(&mut cpu, &program);
+        assert!(!cpu.display[0][63]);
+        assert_eq!(cpu.registers[0xF], 1);
+
+        cpu.display[5][5] = true;
+        run(&mut cpu, &[0x00, 0xE0, 0x00, 0x00]);
+        assert!(cpu.display.iter().flatten().all(|&pixel| !pixel));
+    }
+
+    #[test]
+    fn test_keys_and_timers() {
+        let mut cpu = CPU::new();
+        cpu.keypad[0xA] = true;
+        #[rustfmt::skip]
+        run(&mut cpu, &[
+            0xF0, 0x0A, // LD V0, K
+            0xF0, 0x15, // LD DT, V0
+            0xE0, 0x9E, // SKP V0
+            0x61, 0x01, // LD V1, 1 (skipped)
+        ]);
+
+        assert_eq!(cpu.registers[0], 0xA);
+        assert_eq!(cpu.registers[1], 0);
+
+        cpu.tick_timers();
+        assert_eq!(cpu.delay_timer, 9);
+        assert_eq!(cpu.sound_timer, 0);
+    }
+
+    #[test]
+    fn test_rnd_masks() {
+        let mut cpu = CPU::new();
+        cpu.seed(7);
+        run(&mut cpu, &[0xC0, 0x0F, 0xC1, 0x00]);
+
+        assert!(cpu.registers[0] <= 0x0F);
+        assert_eq!(cpu.registers[1], 0);
+    }
+
+    #[test]
+    fn test_load_rom() {
+        let mut cpu = CPU::new();
+        #[rustfmt::skip]
+        let rom = [
+            0x60, 0x0F, // LD V0, 0xF
+            0xF0, 0x29, // LD F, V0
+            0x12, 0x04, // JP 0x204
+        ];
+        assert!(cpu.load_rom(&rom).is_ok());
+        assert_eq!(cpu.run(), Ok(StepOutcome::Halted));
+
+        assert_eq!(cpu.memory[PROGRAM_START..PROGRAM_START + 6], rom);
+        assert_eq!(cpu.i as usize, FONT_START + 15 * 5);
+        assert_eq!(
+            cpu.memory[cpu.i as usize..cpu.i as usize + 5],
+            [0xF0, 0x80, 0xF0, 0x80, 0x80]
+        );
+
+        assert!(cpu.load_rom(&[0; MAX_ROM_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_stack_faults() {
+        let mut cpu = CPU::new();
+        // CALL 0x000, forever
+        load(&mut cpu, &[0x20, 0x00]);
+        for _ in 0..16 {
+            assert_eq!(cpu.step(), Ok(StepOutcome::Continue));
+        }
+        assert_eq!(
+            cpu.step(),
+            Err(CpuFault::StackOverflow {
+                pc: 0x000,
+                opcode: 0x2000
+            })
+        );
+
+        let mut cpu = CPU::new();
+        load(&mut cpu, &[0x00, 0xEE]);
+        assert_eq!(
+            cpu.run(),
+            Err(CpuFault::StackUnderflow {
+                pc: 0x000,
+                opcode: 0x00EE
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_opcodes() {
+        for opcode in [0x5121u16, 0x8008, 0x9121, 0xE000, 0xF0FF] {
+            let mut cpu = CPU::new();
+            load(&mut cpu, &opcode.to_be_bytes());
+            assert_eq!(
+                cpu.run(),
+                Err(CpuFault::UnknownOpcode { pc: 0x000, opcode })
+            );
+        }
+    }
+
+    #[test]
+    fn test_out_of_bounds_faults() {
+        let mut cpu = CPU::new();
+        #[rustfmt::skip]
+        load(&mut cpu, &[
+            0x60, 0x10, // LD V0, 0x10
+            0xBF, 0xF0, // JP V0, 0xFF0
+        ]);
+        assert_eq!(cpu.run(), Err(CpuFault::PcOutOfBounds { pc: 0x1000 }));
+
+        // the last byte of memory is only half an instruction
+        let mut cpu = CPU::new();
+        load(&mut cpu, &[0x1F, 0xFF]);
+        assert_eq!(cpu.run(), Err(CpuFault::PcOutOfBounds { pc: 0xFFF }));
+
+        let mut cpu = CPU::new();
+        #[rustfmt::skip]
+        load(&mut cpu, &[
+            0xAF, 0xFE, // LD I, 0xFFE
+            0xF0, 0x33, // LD B, V0
+        ]);
+        let fault = CpuFault::MemoryOutOfRange {
+            pc: 0x002,
+            opcode: 0xF033,
+            i: 0xFFE,
+        };
+        assert_eq!(cpu.run(), Err(fault));
+        assert_eq!(cpu.memory[0xFFE..], [0, 0]);
+
+        // the PC stays on the faulting instruction, so fixing I recovers
+        cpu.i = 0x300;
+        assert_eq!(cpu.run(), Ok(StepOutcome::Halted));
+        assert_eq!(cpu.memory[0x300..0x303], [0, 0, 0]);
+    }
 }
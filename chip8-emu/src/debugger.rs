@@ -0,0 +1,605 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    cpu::{CPU, CpuFault, StepOutcome},
+    terminal,
+};
+
+const HELP: &str = "\
+s, step [N]          execute N instructions (default 1)
+c, continue          run until a breakpoint, a watch, a halt, a fault or a key
+                     wait, for at most 10000000 instructions
+b, break ADDR        stop before executing the instruction at ADDR
+d, delete [ADDR]     remove the breakpoint at ADDR, or all of them
+w, watch TARGET      stop when a register (V0-VF) or memory byte (ADDR) changes
+unwatch TARGET       stop watching TARGET
+i, info              list breakpoints and watches
+r, regs              show registers, I, PC, stack pointer and timers
+x, mem ADDR [LEN]    show LEN bytes of memory from ADDR (default 16)
+bt, stack            show the call stack
+screen               show the display
+k, key K             toggle keypad key K (0-F) between up and down
+q, quit              leave the debugger
+
+Addresses are hex, with or without 0x. An empty line repeats the last command.";
+
+// Most instructions `continue` executes before giving the prompt back, so a
+// program stuck in a loop cannot hang the debugger
+const CONTINUE_LIMIT: u64 = 10_000_000;
+
+// Something whose value the debugger keeps an eye on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    Register(usize),
+    Memory(u16),
+}
+
+impl Watch {
+    // A register like `v3` or `VA`, or a memory address
+    fn parse(s: &str) -> Option<Self> {
+        if let Some(reg) = s.strip_prefix(['v', 'V']) {
+            return match usize::from_str_radix(reg, 16) {
+                Ok(reg) if reg < 16 => Some(Watch::Register(reg)),
+                _ => None,
+            };
+        }
+        parse_addr(s).map(Watch::Memory)
+    }
+
+    fn read(self, cpu: &CPU) -> u8 {
+        match self {
+            Watch::Register(reg) => cpu.registers[reg],
+            Watch::Memory(addr) => cpu.memory[addr as usize],
+        }
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watch::Register(reg) => write!(f, "V{:X}", reg),
+            Watch::Memory(addr) => write!(f, "[{:#05x}]", addr),
+        }
+    }
+}
+
+// Why the debugger stopped executing instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pause {
+    // As many instructions as asked for were executed
+    Stepped,
+    // The next instruction has a breakpoint
+    Breakpoint(u16),
+    // A watched register or memory byte changed
+    Watch { watch: Watch, old: u8, new: u8 },
+    // The program halted
+    Halted,
+    // An instruction could not be executed; the PC is still on it
+    Fault(CpuFault),
+    // The next instruction waits for a key, and none is down
+    WaitingForKey,
+}
+
+// Breakpoints and watches over a CPU, and a REPL driving it
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    // Each watch with the value it had when last looked at
+    watches: Vec<(Watch, u8)>,
+    // Instructions executed per tick of the timers
+    per_tick: u64,
+    executed: u64,
+    last_command: String,
+}
+
+impl Debugger {
+    // The timers tick once every `hz / 60` instructions, so programs see
+    // the same timing as when running at `hz`
+    pub fn new(hz: u32) -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            watches: Vec::new(),
+            per_tick: (hz as u64 / 60).max(1),
+            executed: 0,
+            last_command: String::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn add_watch(&mut self, cpu: &CPU, watch: Watch) {
+        if !self.watches.iter().any(|&(w, _)| w == watch) {
+            self.watches.push((watch, watch.read(cpu)));
+        }
+    }
+
+    // Execute up to `steps` instructions, or with `None` until something
+    // else stops execution. Breakpoints are checked after each instruction,
+    // so resuming from one steps off it
+    pub fn resume(&mut self, cpu: &mut CPU, steps: Option<u64>) -> Pause {
+        let mut done = 0;
+        loop {
+            if steps.is_some_and(|steps| done >= steps) {
+                return Pause::Stepped;
+            }
+            // nothing can press a key while this loop runs
+            if waiting_for_key(cpu) {
+                return Pause::WaitingForKey;
+            }
+
+            let outcome = match cpu.step() {
+                Ok(outcome) => outcome,
+                Err(fault) => return Pause::Fault(fault),
+            };
+            done += 1;
+            self.executed += 1;
+            if self.executed.is_multiple_of(self.per_tick) {
+                cpu.tick_timers();
+            }
+
+            for (watch, seen) in &mut self.watches {
+                let value = watch.read(cpu);
+                if value != *seen {
+                    let old = *seen;
+                    *seen = value;
+                    return Pause::Watch {
+                        watch: *watch,
+                        old,
+                        new: value,
+                    };
+                }
+            }
+            if outcome == StepOutcome::Halted {
+                return Pause::Halted;
+            }
+            if self.breakpoints.contains(&cpu.pc()) {
+                return Pause::Breakpoint(cpu.pc());
+            }
+        }
+    }
+
+    // Run one command line, writing what it shows to `out`. Returns false
+    // once the user asks to quit
+    pub fn command<W: Write>(
+        &mut self,
+        cpu: &mut CPU,
+        line: &str,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => {
+                self.last_command = line.to_string();
+                line.to_string()
+            }
+        };
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(true);
+        };
+        let args: Vec<&str> = words.collect();
+
+        match (command, args.as_slice()) {
+            ("s" | "step", []) => {
+                let pause = self.resume(cpu, Some(1));
+                report(cpu, pause, out)?;
+            }
+            ("s" | "step", [n]) => match n.parse() {
+                Ok(n) => {
+                    let pause = self.resume(cpu, Some(n));
+                    report(cpu, pause, out)?;
+                }
+                Err(_) => writeln!(out, "not a number of steps: {}", n)?,
+            },
+            ("c" | "continue", []) => {
+                let pause = self.resume(cpu, Some(CONTINUE_LIMIT));
+                if pause == Pause::Stepped {
+                    writeln!(out, "still running after {} instructions", CONTINUE_LIMIT)?;
+                }
+                report(cpu, pause, out)?;
+            }
+            ("b" | "break", [addr]) => match parse_addr(addr) {
+                Some(addr) => {
+                    self.add_breakpoint(addr);
+                    writeln!(out, "breakpoint at {:#05x}", addr)?;
+                }
+                None => writeln!(out, "not an address: {}", addr)?,
+            },
+            ("d" | "delete", []) => {
+                self.breakpoints.clear();
+                writeln!(out, "deleted all breakpoints")?;
+            }
+            ("d" | "delete", [addr]) => match parse_addr(addr) {
+                Some(addr) if self.breakpoints.remove(&addr) => {
+                    writeln!(out, "deleted breakpoint at {:#05x}", addr)?;
+                }
+                _ => writeln!(out, "no breakpoint at {}", addr)?,
+            },
+            ("w" | "watch", [target]) => match Watch::parse(target) {
+                Some(watch) => {
+                    self.add_watch(cpu, watch);
+                    writeln!(out, "watching {} = {:#04x}", watch, watch.read(cpu))?;
+                }
+                None => writeln!(out, "not a register or address: {}", target)?,
+            },
+            ("unwatch", [target]) => {
+                let watch = Watch::parse(target);
+                let before = self.watches.len();
+                self.watches.retain(|&(w, _)| Some(w) != watch);
+                match self.watches.len() < before {
+                    true => writeln!(out, "stopped watching {}", target)?,
+                    false => writeln!(out, "not watching {}", target)?,
+                }
+            }
+            ("i" | "info", []) => self.info(out)?,
+            ("r" | "regs", []) => regs(cpu, out)?,
+            ("x" | "mem", [addr]) => mem(cpu, addr, "16", out)?,
+            ("x" | "mem", [addr, len]) => mem(cpu, addr, len, out)?,
+            ("bt" | "stack", []) => call_stack(cpu, out)?,
+            ("screen", []) => write!(out, "{}", terminal::display_text(&cpu.display))?,
+            ("k" | "key", [key]) => match u8::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => {
+                    let down = &mut cpu.keypad[key as usize];
+                    *down = !*down;
+                    let state = if *down { "down" } else { "up" };
+                    writeln!(out, "key {:X} is {}", key, state)?;
+                }
+                _ => writeln!(out, "not a key: {}", key)?,
+            },
+            ("h" | "help", []) => writeln!(out, "{}", HELP)?,
+            ("q" | "quit", []) => return Ok(false),
+            _ => writeln!(out, "unknown command: {} (try help)", line)?,
+        }
+
+        Ok(true)
+    }
+
+    // List breakpoints and watches
+    fn info<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.breakpoints.is_empty() && self.watches.is_empty() {
+            return writeln!(out, "no breakpoints or watches");
+        }
+        for addr in &self.breakpoints {
+            writeln!(out, "breakpoint at {:#05x}", addr)?;
+        }
+        for (watch, value) in &self.watches {
+            writeln!(out, "watching {} = {:#04x}", watch, value)?;
+        }
+        Ok(())
+    }
+}
+
+// Run the debugger on stdin and stdout until quit or end of input
+pub fn run(cpu: &mut CPU, hz: u32) -> io::Result<()> {
+    let mut debugger = Debugger::new(hz);
+    let stdin = io::stdin();
+    let mut out = io::stdout();
+
+    writeln!(out, "{}", location(cpu))?;
+    loop {
+        write!(out, "(chip8) ")?;
+        out.flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        if !debugger.command(cpu, &line, &mut out)? {
+            return Ok(());
+        }
+    }
+}
+
+// Say why execution stopped, and where
+fn report<W: Write>(cpu: &CPU, pause: Pause, out: &mut W) -> io::Result<()> {
+    match pause {
+        Pause::Stepped => {}
+        Pause::Breakpoint(addr) => writeln!(out, "breakpoint at {:#05x}", addr)?,
+        Pause::Watch { watch, old, new } => {
+            writeln!(out, "{} changed from {:#04x} to {:#04x}", watch, old, new)?;
+        }
+        Pause::Halted => writeln!(out, "program halted")?,
+        Pause::Fault(fault) => writeln!(out, "fault: {}", fault)?,
+        Pause::WaitingForKey => writeln!(out, "waiting for a key (press one with key K)")?,
+    }
+    writeln!(out, "{}", location(cpu))
+}
+
+// The instruction at the PC
+fn location(cpu: &CPU) -> String {
+    instruction_at(cpu, cpu.pc())
+}
+
+// Whether the instruction at the PC is Fx0A with no key down, which would
+// execute forever
+fn waiting_for_key(cpu: &CPU) -> bool {
+    let pc = cpu.pc() as usize;
+    let waits = match cpu.memory.get(pc..pc + 2) {
+        Some(&[op_byte1, op_byte2]) => op_byte1 & 0xF0 == 0xF0 && op_byte2 == 0x0A,
+        _ => false,
+    };
+    waits && !cpu.keypad.iter().any(|&down| down)
+}
+
+// An instruction's address, opcode and disassembly
+fn instruction_at(cpu: &CPU, addr: u16) -> String {
+    let addr_ = addr as usize;
+    match cpu.memory.get(addr_..addr_ + 2) {
+        Some(&[op_byte1, op_byte2]) => {
+            let opcode = (op_byte1 as u16) << 8 | op_byte2 as u16;
+            format!("{:#05x}: {:04X}  {}", addr, opcode, disassemble(opcode))
+        }
+        _ => format!("{:#05x}: past the end of memory", addr),
+    }
+}
+
+fn regs<W: Write>(cpu: &CPU, out: &mut W) -> io::Result<()> {
+    for (row, values) in cpu.registers.chunks(8).enumerate() {
+        let line: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(n, value)| format!("V{:X} {:02X}", row * 8 + n, value))
+            .collect();
+        writeln!(out, "{}", line.join("  "))?;
+    }
+    writeln!(
+        out,
+        "PC {:#05x}  I {:#05x}  SP {}  DT {}  ST {}",
+        cpu.pc(),
+        cpu.i,
+        cpu.stack_pointer(),
+        cpu.delay_timer,
+        cpu.sound_timer
+    )
+}
+
+fn mem<W: Write>(cpu: &CPU, addr: &str, len: &str, out: &mut W) -> io::Result<()> {
+    let (Some(addr), Ok(len)) = (parse_addr(addr), len.parse::<usize>()) else {
+        return writeln!(out, "usage: mem ADDR [LEN]");
+    };
+
+    let start = addr as usize;
+    if start >= cpu.memory.len() {
+        return writeln!(out, "{:#05x} is past the end of memory", start);
+    }
+    let end = start.saturating_add(len).min(cpu.memory.len());
+    for (row, bytes) in cpu.memory[start..end].chunks(16).enumerate() {
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        writeln!(out, "{:#05x}: {}", start + row * 16, hex.join(" "))?;
+    }
+    Ok(())
+}
+
+// The current instruction, then each CALL that led to it, innermost first
+fn call_stack<W: Write>(cpu: &CPU, out: &mut W) -> io::Result<()> {
+    writeln!(out, "#0  {}", location(cpu))?;
+    for (depth, ret) in cpu.stack().iter().rev().enumerate() {
+        writeln!(
+            out,
+            "#{}  {}",
+            depth + 1,
+            instruction_at(cpu, ret.wrapping_sub(2))
+        )?;
+    }
+    Ok(())
+}
+
+// A memory address in hex, with or without 0x
+fn parse_addr(s: &str) -> Option<u16> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    match u16::from_str_radix(digits, 16) {
+        Ok(addr) if addr < 0x1000 => Some(addr),
+        _ => None,
+    }
+}
+
+// The instruction `opcode` decodes to, in the notation of Cowgod's
+// technical reference
+pub fn disassemble(opcode: u16) -> String {
+    let vx = (opcode & 0x0F00) >> 8;
+    let vy = (opcode & 0x00F0) >> 4;
+    let addr = opcode & 0x0FFF;
+    let kk = opcode & 0x00FF;
+    let op_minor = opcode & 0x000F;
+
+    match opcode {
+        0x0000..=0x0FFF => match opcode {
+            0x0000 => "HALT".to_string(),
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS {:#05x}", addr),
+        },
+        0x1000..=0x1FFF => format!("JP {:#05x}", addr),
+        0x2000..=0x2FFF => format!("CALL {:#05x}", addr),
+        0x3000..=0x3FFF => format!("SE V{:X}, {:#04x}", vx, kk),
+        0x4000..=0x4FFF => format!("SNE V{:X}, {:#04x}", vx, kk),
+        0x5000..=0x5FFF => match op_minor {
+            0x00 => format!("SE V{:X}, V{:X}", vx, vy),
+            _ => "???".to_string(),
+        },
+        0x6000..=0x6FFF => format!("LD V{:X}, {:#04x}", vx, kk),
+        0x7000..=0x7FFF => format!("ADD V{:X}, {:#04x}", vx, kk),
+        0x8000..=0x8FFF => match op_minor {
+            0x00 => format!("LD V{:X}, V{:X}", vx, vy),
+            0x01 => format!("OR V{:X}, V{:X}", vx, vy),
+            0x02 => format!("AND V{:X}, V{:X}", vx, vy),
+            0x03 => format!("XOR V{:X}, V{:X}", vx, vy),
+            0x04 => format!("ADD V{:X}, V{:X}", vx, vy),
+            0x05 => format!("SUB V{:X}, V{:X}", vx, vy),
+            0x06 => format!("SHR V{:X}", vx),
+            0x07 => format!("SUBN V{:X}, V{:X}", vx, vy),
+            0x0E => format!("SHL V{:X}", vx),
+            _ => "???".to_string(),
+        },
+        0x9000..=0x9FFF => match op_minor {
+            0x00 => format!("SNE V{:X}, V{:X}", vx, vy),
+            _ => "???".to_string(),
+        },
+        0xA000..=0xAFFF => format!("LD I, {:#05x}", addr),
+        0xB000..=0xBFFF => format!("JP V0, {:#05x}", addr),
+        0xC000..=0xCFFF => format!("RND V{:X}, {:#04x}", vx, kk),
+        0xD000..=0xDFFF => format!("DRW V{:X}, V{:X}, {}", vx, vy, op_minor),
+        0xE000..=0xEFFF => match kk {
+            0x9E => format!("SKP V{:X}", vx),
+            0xA1 => format!("SKNP V{:X}", vx),
+            _ => "???".to_string(),
+        },
+        0xF000..=0xFFFF => match kk {
+            0x07 => format!("LD V{:X}, DT", vx),
+            0x0A => format!("LD V{:X}, K", vx),
+            0x15 => format!("LD DT, V{:X}", vx),
+            0x18 => format!("LD ST, V{:X}", vx),
+            0x1E => format!("ADD I, V{:X}", vx),
+            0x29 => format!("LD F, V{:X}", vx),
+            0x33 => format!("LD B, V{:X}", vx),
+            0x55 => format!("LD [I], V{:X}", vx),
+            0x65 => format!("LD V{:X}, [I]", vx),
+            _ => "???".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Debugger, Pause, Watch, disassemble};
+    use crate::cpu::CPU;
+
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 14] = [
+        0x60, 0x05, // 0x200: LD V0, 0x05
+        0x22, 0x08, // 0x202: CALL 0x208
+        0x12, 0x06, // 0x204: JP 0x206
+        0x00, 0x00, // 0x206: HALT
+        0x70, 0x01, // 0x208: ADD V0, 0x01
+        0x71, 0x02, // 0x20a: ADD V1, 0x02
+        0x00, 0xEE, // 0x20c: RET
+    ];
+
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new();
+        assert!(cpu.load_rom(&PROGRAM).is_ok());
+        cpu
+    }
+
+    // Run `commands` and return what the last one showed
+    fn commands(debugger: &mut Debugger, cpu: &mut CPU, commands: &[&str]) -> String {
+        let mut out = Vec::new();
+        for command in commands {
+            out.clear();
+            assert!(matches!(debugger.command(cpu, command, &mut out), Ok(true)));
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    #[test]
+    fn test_breakpoints_and_watches() {
+        let mut cpu = cpu();
+        let mut debugger = Debugger::new(700);
+        debugger.add_breakpoint(0x20a);
+        debugger.add_watch(&cpu, Watch::Register(1));
+
+        assert_eq!(debugger.resume(&mut cpu, None), Pause::Breakpoint(0x20a));
+        assert_eq!(cpu.registers[0], 6);
+
+        let changed = Pause::Watch {
+            watch: Watch::Register(1),
+            old: 0,
+            new: 2,
+        };
+        assert_eq!(debugger.resume(&mut cpu, None), changed);
+        assert_eq!(cpu.pc(), 0x20c);
+
+        assert_eq!(debugger.resume(&mut cpu, Some(1)), Pause::Stepped);
+        assert_eq!(cpu.pc(), 0x204);
+        assert_eq!(debugger.resume(&mut cpu, None), Pause::Halted);
+        assert_eq!(cpu.pc(), 0x206);
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut cpu = cpu();
+        let mut debugger = Debugger::new(700);
+
+        let shown = commands(&mut debugger, &mut cpu, &["break 0x20a", "continue", "bt"]);
+        assert_eq!(
+            shown,
+            "#0  0x20a: 7102  ADD V1, 0x02\n#1  0x202: 2208  CALL 0x208\n"
+        );
+
+        // an empty line repeats the last command
+        let shown = commands(&mut debugger, &mut cpu, &["s", ""]);
+        assert_eq!(shown, "0x204: 1206  JP 0x206\n");
+
+        let shown = commands(&mut debugger, &mut cpu, &["x 208 4"]);
+        assert_eq!(shown, "0x208: 70 01 71 02\n");
+
+        // a length running past the end of memory stops there
+        let shown = commands(&mut debugger, &mut cpu, &["x 1 18446744073709551615"]);
+        assert!(shown.starts_with("0x001: "));
+        assert_eq!(shown.lines().count(), 256);
+        assert!(shown.ends_with("0xff1: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00\n"));
+
+        let shown = commands(&mut debugger, &mut cpu, &["key f"]);
+        assert_eq!(shown, "key F is down\n");
+        assert!(cpu.keypad[0xF]);
+
+        let mut out = Vec::new();
+        assert!(matches!(
+            debugger.command(&mut cpu, "quit", &mut out),
+            Ok(false)
+        ));
+    }
+
+    #[test]
+    fn test_continue_stops_at_key_wait_and_loops() {
+        let mut cpu = CPU::new();
+        assert!(
+            cpu.load_rom(&[
+                0xF3, 0x0A, // 0x200: LD V3, K
+                0x12, 0x04, // 0x202: JP 0x204
+                0x70, 0x01, // 0x204: ADD V0, 0x01
+                0x12, 0x04, // 0x206: JP 0x204
+            ])
+            .is_ok()
+        );
+        let mut debugger = Debugger::new(700);
+
+        assert_eq!(debugger.resume(&mut cpu, None), Pause::WaitingForKey);
+        assert_eq!(cpu.pc(), 0x200);
+        let shown = commands(&mut debugger, &mut cpu, &["continue"]);
+        assert_eq!(
+            shown,
+            "waiting for a key (press one with key K)\n0x200: F30A  LD V3, K\n"
+        );
+
+        let shown = commands(&mut debugger, &mut cpu, &["key 7", "continue"]);
+        assert!(shown.starts_with("still running after 10000000 instructions\n"));
+        assert_eq!(cpu.registers[3], 7);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let cases = [
+            (0x00E0, "CLS"),
+            (0x2345, "CALL 0x345"),
+            (0x8AB6, "SHR VA"),
+            (0xD125, "DRW V1, V2, 5"),
+            (0xF355, "LD [I], V3"),
+            (0x5120, "SE V1, V2"),
+            (0x9120, "SNE V1, V2"),
+            (0xE0FF, "???"),
+            (0x5121, "???"),
+            (0x9121, "???"),
+        ];
+
+        for (opcode, text) in cases {
+            assert_eq!(disassemble(opcode), text);
+        }
+    }
+}
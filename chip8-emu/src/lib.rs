@@ -1 +1,5 @@
+pub mod args;
+pub mod clock;
 pub mod cpu;
+pub mod debugger;
+pub mod terminal;
@@ -1,38 +1,60 @@
-use std::error::Error;
+use std::{error::Error, fs};
 
-use chip8_emu::cpu::CPU;
+use chip8_emu::{
+    args::Args,
+    clock::{Clock, Stop},
+    cpu::CPU,
+    debugger, terminal,
+};
+use clap::Parser;
 
-#[rustfmt::skip]
 fn main() -> Result<(), Box<dyn Error>> {
+    let Args {
+        rom,
+        hz,
+        steps,
+        headless,
+        debug,
+    } = Args::parse();
+
+    let program = fs::read(&rom).map_err(|e| format!("{}: {}", rom.display(), e))?;
     let mut cpu = CPU::new();
-
-    // Initialize registers with test values
-    cpu.registers[0] = 5;
-    cpu.registers[1] = 10;
-    cpu.registers[2] = 10;
-    cpu.registers[3] = 250;
-
-    let mem = &mut cpu.memory;
-
-    // Program at 0x000: Two CALL instructions to 0x100
-    mem[0x000] = 0x21; mem[0x001] = 0x00;  // CALL 0x100
-    mem[0x002] = 0x21; mem[0x003] = 0x00;  // CALL 0x100
-    mem[0x004] = 0x00; mem[0x005] = 0x00;  // Exit
-
-    // Subroutine at 0x100: ADD operations
-    mem[0x100] = 0x80; mem[0x101] = 0x14;  // ADD V0, V1 (V0 = V0 + V1)
-    mem[0x102] = 0x80; mem[0x103] = 0x14;  // ADD V0, V1 (V0 = V0 + V1)
-    mem[0x104] = 0x00; mem[0x105] = 0xEE;  // RET (return from subroutine)
-
-    // Print initial register state
-    dbg!(cpu.registers);
-
-    // Run the program
-    cpu.run();
-
-    // Print final register state
-    // Expected: V0 = 5 + (10 * 2) + (10 * 2) = 45 (called twice)
-    dbg!(&cpu.registers);
+    cpu.load_rom(&program)?;
+
+    if debug {
+        debugger::run(&mut cpu, hz)?;
+        return Ok(());
+    }
+
+    let mut clock = Clock::new(hz, steps);
+    let stopped = if headless {
+        let stop = loop {
+            if let Some(stop) = clock.frame(&mut cpu) {
+                break stop;
+            }
+            clock.wait();
+        };
+        print!("{}", terminal::display_text(&cpu.display));
+        Some(stop)
+    } else {
+        terminal::run(&mut cpu, &mut clock)?
+    };
+
+    let state = match stopped {
+        Some(Stop::Halted) => "halted",
+        Some(Stop::StepLimit) => "stopped",
+        Some(Stop::Fault(_)) => "faulted",
+        None => "quit",
+    };
+    println!("{} after {} instructions", state, clock.executed());
+    println!("I  = {:#05x}", cpu.i);
+    for (n, value) in cpu.registers.iter().enumerate() {
+        println!("V{:X} = {:#04x}", n, value);
+    }
+
+    if let Some(Stop::Fault(fault)) = stopped {
+        return Err(fault.to_string().into());
+    }
 
     Ok(())
 }
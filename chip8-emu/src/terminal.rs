@@ -0,0 +1,262 @@
+use std::{
+    io::{self, Stdout, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::Print,
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use crate::{
+    clock::{Clock, FRAME, Stop},
+    cpu::{CPU, DISPLAY_HEIGHT, DISPLAY_WIDTH, Display},
+};
+
+// How long a key counts as held after it was pressed, in terminals that
+// only report presses. Autorepeat keeps a held key pressed after that
+const KEY_HOLD: Duration = Duration::from_millis(200);
+
+// Full-screen terminal frontend: draws the display with half-block
+// characters and feeds key presses to the keypad. The terminal is restored
+// when this is dropped
+#[derive(Debug)]
+pub struct Terminal {
+    out: Stdout,
+    // Whether the terminal reports key releases
+    releases: bool,
+    // When each keypad key was last pressed, if it is still down
+    held: [Option<Instant>; 16],
+    // What was drawn last, to skip redrawing an unchanged screen
+    shown: Option<(Display, String)>,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut term = Self {
+            out: io::stdout(),
+            releases: false,
+            held: [None; 16],
+            shown: None,
+        };
+
+        execute!(
+            term.out,
+            EnterAlternateScreen,
+            cursor::Hide,
+            Clear(ClearType::All)
+        )?;
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            execute!(
+                term.out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+            term.releases = true;
+        }
+
+        Ok(term)
+    }
+
+    // Handle pending key events without blocking and update `keypad`.
+    // Returns false once Esc or Ctrl-C was pressed
+    pub fn poll_input(&mut self, keypad: &mut [bool; 16]) -> io::Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            }) = event::read()?
+            else {
+                continue;
+            };
+
+            match code {
+                KeyCode::Esc => return Ok(false),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(false);
+                }
+                KeyCode::Char(c) => {
+                    if let Some(key) = keypad_key(c) {
+                        self.held[key] = match kind {
+                            KeyEventKind::Release => None,
+                            _ => Some(Instant::now()),
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (down, held) in keypad.iter_mut().zip(&mut self.held) {
+            if !self.releases && held.is_some_and(|at| at.elapsed() >= KEY_HOLD) {
+                *held = None;
+            }
+            *down = held.is_some();
+        }
+
+        Ok(true)
+    }
+
+    // Draw the display, two pixel rows per line of text, with `status`
+    // underneath
+    pub fn draw(&mut self, display: &Display, status: &str) -> io::Result<()> {
+        if let Some((shown, shown_status)) = &self.shown
+            && shown == display
+            && shown_status == status
+        {
+            return Ok(());
+        }
+
+        for (y, rows) in display.chunks(2).enumerate() {
+            let bottom = rows.get(1);
+            let line: String = (0..DISPLAY_WIDTH)
+                .map(|x| half_block(rows[0][x], bottom.is_some_and(|row| row[x])))
+                .collect();
+            queue!(self.out, cursor::MoveTo(0, y as u16), Print(line))?;
+        }
+        queue!(
+            self.out,
+            cursor::MoveTo(0, DISPLAY_HEIGHT.div_ceil(2) as u16),
+            Clear(ClearType::CurrentLine),
+            Print(status)
+        )?;
+        self.out.flush()?;
+
+        self.shown = Some((*display, status.to_string()));
+        Ok(())
+    }
+
+    // Ring the terminal bell
+    pub fn beep(&mut self) -> io::Result<()> {
+        execute!(self.out, Print('\x07'))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// The display as text, one character per pixel, in a box
+pub fn display_text(display: &Display) -> String {
+    let border = format!("+{}+\n", "-".repeat(DISPLAY_WIDTH));
+    let mut text = border.clone();
+    for row in display {
+        text.push('|');
+        text.extend(row.iter().map(|&pixel| if pixel { '█' } else { ' ' }));
+        text.push_str("|\n");
+    }
+    text.push_str(&border);
+    text
+}
+
+// The character showing a pixel above another
+fn half_block(top: bool, bottom: bool) -> char {
+    match (top, bottom) {
+        (true, true) => '█',
+        (true, false) => '▀',
+        (false, true) => '▄',
+        (false, false) => ' ',
+    }
+}
+
+// The hex keypad on the left-hand side of a QWERTY keyboard:
+//
+//   1 2 3 C        1 2 3 4
+//   4 5 6 D   ->   Q W E R
+//   7 8 9 E        A S D F
+//   A 0 B F        Z X C V
+fn keypad_key(c: char) -> Option<usize> {
+    let key = match c.to_ascii_lowercase() {
+        '1' => 0x1,
+        '2' => 0x2,
+        '3' => 0x3,
+        '4' => 0xC,
+        'q' => 0x4,
+        'w' => 0x5,
+        'e' => 0x6,
+        'r' => 0xD,
+        'a' => 0x7,
+        's' => 0x8,
+        'd' => 0x9,
+        'f' => 0xE,
+        'z' => 0xA,
+        'x' => 0x0,
+        'c' => 0xB,
+        'v' => 0xF,
+        _ => return None,
+    };
+    Some(key)
+}
+
+// Run `cpu` in the terminal until Esc is pressed, keeping the last screen
+// up once the program stops. Returns why the program stopped, if it did
+pub fn run(cpu: &mut CPU, clock: &mut Clock) -> io::Result<Option<Stop>> {
+    let mut term = Terminal::open()?;
+    let mut stopped = None;
+    let mut sounding = false;
+
+    while term.poll_input(&mut cpu.keypad)? {
+        if stopped.is_none() {
+            stopped = clock.frame(cpu);
+        }
+
+        let sound = cpu.sound_timer > 0;
+        if sound && !sounding {
+            term.beep()?;
+        }
+        sounding = sound;
+
+        let status = match stopped {
+            None => "Esc to quit".to_string(),
+            Some(Stop::Halted) => "Halted. Esc to quit".to_string(),
+            Some(Stop::StepLimit) => "Step limit reached. Esc to quit".to_string(),
+            Some(Stop::Fault(fault)) => format!("Fault: {}. Esc to quit", fault),
+        };
+        term.draw(&cpu.display, &status)?;
+
+        match stopped {
+            None => clock.wait(),
+            Some(_) => thread::sleep(FRAME),
+        }
+    }
+
+    Ok(stopped)
+}
+
+#[cfg(test)]
+mod test {
+    use super::keypad_key;
+
+    #[test]
+    fn test_keypad_layout() {
+        let rows = ["1234", "qwer", "asdf", "zxcv"];
+        let keypad = [
+            [0x1, 0x2, 0x3, 0xC],
+            [0x4, 0x5, 0x6, 0xD],
+            [0x7, 0x8, 0x9, 0xE],
+            [0xA, 0x0, 0xB, 0xF],
+        ];
+
+        for (row, keys) in rows.iter().zip(keypad) {
+            let mapped: Vec<_> = row.chars().map(keypad_key).collect();
+            assert_eq!(mapped, keys.map(Some));
+        }
+        assert_eq!(keypad_key('Q'), Some(0x4));
+        assert_eq!(keypad_key('p'), None);
+    }
+}